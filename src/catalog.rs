@@ -0,0 +1,37 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+
+use crate::ucam_cloud_api::{CacheInfo, CourseGeneralInfo};
+
+/// The catalog as written to `all-courses.json` by `dump`, borrowing the courses when saving.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CachedCatalog<C> {
+    cache_info: CacheInfo,
+    courses: C,
+}
+
+/// The catalog cached at `path`, if it hasn't expired yet. A file of an older version holding
+/// only the courses counts as expired.
+pub fn load_catalog(path: &Path) -> Option<Vec<CourseGeneralInfo>> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let cached: CachedCatalog<Vec<CourseGeneralInfo>> = serde_json::from_str(&content).ok()?;
+    let expires_at = DateTime::parse_from_rfc3339(&cached.cache_info.expires_at).ok()?;
+    (expires_at > Utc::now()).then_some(cached.courses)
+}
+
+/// Caches `courses` at `path` for `ttl`.
+pub fn save_catalog(path: &Path, courses: &[CourseGeneralInfo], ttl: Duration) -> Result<()> {
+    let fetched_at = Utc::now();
+    let cached = CachedCatalog {
+        cache_info: CacheInfo {
+            is_cached: true,
+            cached_at: fetched_at.to_rfc3339(),
+            expires_at: (fetched_at + ttl).to_rfc3339(),
+        },
+        courses,
+    };
+    std::fs::write(path, serde_json::to_string_pretty(&cached)?)
+        .with_context(|| format!("Failed to write \"{}\"", path.display()))
+}
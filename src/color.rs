@@ -0,0 +1,33 @@
+use std::{io::IsTerminal, sync::OnceLock};
+
+use owo_colors::OwoColorize;
+
+/// What a printed value means, colored accordingly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tone {
+    Success,
+    /// Waiting, retrying or otherwise not done yet.
+    Pending,
+    Error,
+}
+
+/// Whether stdout gets colors, never when it isn't a terminal or `NO_COLOR` is set.
+fn enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| {
+        std::io::stdout().is_terminal()
+            && std::env::var_os("NO_COLOR").is_none_or(|no_color| no_color.is_empty())
+    })
+}
+
+/// `text` colored for `tone`, or as is when colors are disabled.
+pub fn paint(text: &str, tone: Tone) -> String {
+    if !enabled() {
+        return text.to_string();
+    }
+    match tone {
+        Tone::Success => text.green().to_string(),
+        Tone::Pending => text.yellow().to_string(),
+        Tone::Error => text.red().to_string(),
+    }
+}
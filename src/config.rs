@@ -0,0 +1,413 @@
+use std::collections::{HashMap, hash_map::Entry};
+
+use anyhow::{Context, Result, bail};
+use tracing::warn;
+
+pub const DEFAULT_CONFIG_PATH: &str = "./config.toml";
+
+#[derive(Debug, serde::Deserialize)]
+pub struct Config {
+    /// Course code (eg. `"1372-1-1"`) -> preferred section names, most preferred first.
+    #[serde(deserialize_with = "unique_keys")]
+    pub preferred_sections: HashMap<String, Vec<String>>,
+    /// Course code -> how its preferred section names are matched.
+    #[serde(default, deserialize_with = "unique_keys")]
+    pub match_mode: HashMap<String, MatchMode>,
+    /// Course code -> how long to wait before polling it again while none of its preferred
+    /// sections have a free seat, instead of `waiting_poll_interval_ms`.
+    #[serde(default, deserialize_with = "unique_keys")]
+    pub poll_interval_ms: HashMap<String, u64>,
+    /// Course code -> faculty preferences of the course.
+    #[serde(default, deserialize_with = "unique_keys")]
+    pub faculty: HashMap<String, FacultyPreference>,
+    /// Faculty names or codes whose sections are never selected, in any course.
+    #[serde(default)]
+    pub avoid_faculty: Vec<String>,
+    /// How many failed select attempts of a course are retried before giving up on it.
+    #[serde(default = "default_max_select_attempts")]
+    pub max_select_attempts: u32,
+    /// How many poll cycles of a course, with or without a select, are done before giving up on
+    /// it, unlimited when unset.
+    pub max_attempts: Option<u32>,
+    /// How long to wait before polling a course again while it has no sections.
+    #[serde(default = "default_empty_poll_interval_ms")]
+    pub empty_poll_interval_ms: u64,
+    /// How long to wait before polling a course again while none of its preferred sections have
+    /// a free seat.
+    #[serde(default = "default_waiting_poll_interval_ms")]
+    pub waiting_poll_interval_ms: u64,
+    /// Upper bound of the backed off waiting poll interval.
+    #[serde(default = "default_max_waiting_poll_interval_ms")]
+    pub max_waiting_poll_interval_ms: u64,
+    /// Select every preferred section with a free seat at once, keeping the first accepted one.
+    #[serde(default)]
+    pub race_preferred_sections: bool,
+    /// Join the waitlist of the most preferred section once none of the preferred sections had a
+    /// free seat for the grace period.
+    #[serde(default)]
+    pub allow_waitlist: bool,
+    #[serde(default = "default_waitlist_grace_period_secs")]
+    pub waitlist_grace_period_secs: u64,
+    /// Which of the preferred sections with a free seat is tried first.
+    #[serde(default)]
+    pub prefer: SeatStrategy,
+    /// The student's department as the section quotas name it (eg. `"CSE"`), sections with free
+    /// seats in its quota are preferred.
+    pub department: Option<String>,
+    /// How long a request may take before it's retried as timed out.
+    #[serde(default = "default_request_timeout_ms")]
+    pub request_timeout_ms: u64,
+    /// How many courses poll the server at once, the others wait for their turn.
+    #[serde(default = "default_max_concurrent_courses")]
+    pub max_concurrent_courses: usize,
+    /// Sent instead of a randomly spoofed User-Agent.
+    pub user_agent: Option<String>,
+    /// Every request is sent through this proxy, eg. `"socks5://127.0.0.1:1080"`.
+    pub proxy: Option<String>,
+    /// UTC offset the times are shown at (eg. `"+06:00"`), the system's timezone when unset.
+    pub timezone: Option<String>,
+    /// How many requests in a row have to fail before every request is paused, zero never pauses.
+    #[serde(default = "default_circuit_breaker_failures")]
+    pub circuit_breaker_failures: u32,
+    /// How long the requests are paused before probing whether the server is back.
+    #[serde(default = "default_circuit_breaker_cooldown_ms")]
+    pub circuit_breaker_cooldown_ms: u64,
+    /// Ask the server whether selecting is allowed before every select, skipping it with the
+    /// server's explanation when it isn't.
+    #[serde(default)]
+    pub check_selection_allowed: bool,
+    /// Log out once the selection run is over, Ctrl+C included.
+    #[serde(default)]
+    pub logout_on_exit: bool,
+    /// Discord webhook notified on every enrollment and whenever a seat opens in a full preferred
+    /// section.
+    pub discord_webhook_url: Option<String>,
+    /// Telegram chat notified like the Discord webhook, and when a selection window opens.
+    pub telegram: Option<TelegramConfig>,
+}
+
+/// Faculty names or codes, both matched ignoring case.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct FacultyPreference {
+    /// Sections of these faculty are tried first, most preferred first.
+    #[serde(default)]
+    pub preferred_faculty: Vec<String>,
+    /// Sections of these faculty are never selected.
+    #[serde(default)]
+    pub avoid_faculty: Vec<String>,
+}
+
+impl FacultyPreference {
+    /// Position of the section's faculty in the preferred list, if it's there.
+    pub fn rank(&self, faculty_name: &str, faculty_code: &str) -> Option<usize> {
+        self.preferred_faculty
+            .iter()
+            .position(|f| faculty_matches(f, faculty_name, faculty_code))
+    }
+
+    pub fn avoids(&self, faculty_name: &str, faculty_code: &str) -> bool {
+        self.avoid_faculty
+            .iter()
+            .any(|f| faculty_matches(f, faculty_name, faculty_code))
+    }
+}
+
+fn faculty_matches(faculty: &str, faculty_name: &str, faculty_code: &str) -> bool {
+    let faculty = faculty.trim();
+    faculty.eq_ignore_ascii_case(faculty_name.trim())
+        || (!faculty_code.is_empty() && faculty.eq_ignore_ascii_case(faculty_code.trim()))
+}
+
+/// How a preferred section name is matched against the section names.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMode {
+    /// The section name contains it, ignoring case.
+    #[default]
+    Contains,
+    /// The section name is it, ignoring case.
+    Exact,
+    /// The section name matches it as a regex.
+    Regex,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeatStrategy {
+    /// The order of the preferred sections in the config.
+    #[default]
+    ConfigOrder,
+    /// The section with the most free seats.
+    MostSeats,
+    /// The section with the shortest waitlist.
+    LeastWaitlist,
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub struct TelegramConfig {
+    pub bot_token: String,
+    pub chat_id: String,
+}
+
+impl std::fmt::Debug for TelegramConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TelegramConfig")
+            .field("bot_token", &crate::ucam_cloud_api::REDACTED)
+            .field("chat_id", &self.chat_id)
+            .finish()
+    }
+}
+
+fn default_max_select_attempts() -> u32 {
+    5
+}
+
+fn default_empty_poll_interval_ms() -> u64 {
+    1000
+}
+
+fn default_waiting_poll_interval_ms() -> u64 {
+    10_000
+}
+
+fn default_max_waiting_poll_interval_ms() -> u64 {
+    60_000
+}
+
+fn default_waitlist_grace_period_secs() -> u64 {
+    300
+}
+
+fn default_request_timeout_ms() -> u64 {
+    15_000
+}
+
+fn default_max_concurrent_courses() -> usize {
+    4
+}
+
+fn default_circuit_breaker_failures() -> u32 {
+    5
+}
+
+fn default_circuit_breaker_cooldown_ms() -> u64 {
+    30_000
+}
+
+/// Deserializes a map, failing on a duplicate key instead of keeping the last value like
+/// `HashMap` does, JSON allows them.
+fn unique_keys<'de, D, V>(deserializer: D) -> std::result::Result<HashMap<String, V>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    V: serde::Deserialize<'de>,
+{
+    struct UniqueKeys<V>(std::marker::PhantomData<V>);
+
+    impl<'de, V: serde::Deserialize<'de>> serde::de::Visitor<'de> for UniqueKeys<V> {
+        type Value = HashMap<String, V>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a map keyed by course code")
+        }
+
+        fn visit_map<A: serde::de::MapAccess<'de>>(
+            self,
+            mut map: A,
+        ) -> std::result::Result<Self::Value, A::Error> {
+            let mut values = HashMap::new();
+            while let Some((key, value)) = map.next_entry::<String, V>()? {
+                match values.entry(key) {
+                    Entry::Occupied(entry) => {
+                        return Err(serde::de::Error::custom(format!(
+                            "duplicate course code \"{}\"",
+                            entry.key()
+                        )));
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(value);
+                    }
+                }
+            }
+            Ok(values)
+        }
+    }
+
+    deserializer.deserialize_map(UniqueKeys(std::marker::PhantomData))
+}
+
+/// Whether `code` looks like a course code, eg. `"1372-1-1"`.
+pub fn is_valid_course_code(code: &str) -> bool {
+    let is_number =
+        |part: &str, len: usize| part.len() == len && part.bytes().all(|c| c.is_ascii_digit());
+    match code.split('-').collect::<Vec<_>>().as_slice() {
+        [number, a, b] => is_number(number, 4) && is_number(a, 1) && is_number(b, 1),
+        _ => false,
+    }
+}
+
+/// Every malformed course code the config uses, quoted and sorted.
+fn malformed_course_codes(config: &Config) -> Vec<String> {
+    let mut malformed: Vec<_> = config
+        .preferred_sections
+        .keys()
+        .chain(config.faculty.keys())
+        .chain(config.match_mode.keys())
+        .chain(config.poll_interval_ms.keys())
+        .filter(|code| !is_valid_course_code(code))
+        .map(|code| format!("\"{code}\""))
+        .collect();
+    malformed.sort();
+    malformed.dedup();
+    malformed
+}
+
+/// Fails listing every malformed course code the config uses.
+fn validate_course_codes(config: &Config) -> Result<()> {
+    let malformed = malformed_course_codes(config);
+    if !malformed.is_empty() {
+        bail!(
+            "Malformed course codes {}, expected codes like \"1372-1-1\"",
+            malformed.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Every problem of the config that shows without asking the server, sorted. Duplicate keys
+/// already fail reading it.
+pub fn config_problems(config: &Config) -> Vec<String> {
+    let mut problems: Vec<_> = malformed_course_codes(config)
+        .into_iter()
+        .map(|code| format!("Malformed course code {code}, expected codes like \"1372-1-1\""))
+        .collect();
+    let mut empty: Vec<_> = config
+        .preferred_sections
+        .iter()
+        .filter(|(_, sections)| sections.is_empty())
+        .map(|(course_code, _)| format!("No preferred sections for course \"{course_code}\""))
+        .collect();
+    empty.sort();
+    problems.extend(empty);
+    problems
+}
+
+/// Loads the config from a `.toml` file, or a `.json` file if the path ends with `.json`.
+pub fn load_config(path: &str) -> Result<Config> {
+    let config = read_config(path)?;
+    validate_course_codes(&config).with_context(|| format!("Invalid config file \"{path}\""))?;
+    Ok(config)
+}
+
+/// Reads the config like [`load_config`] without checking its course codes.
+pub fn read_config(path: &str) -> Result<Config> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file \"{path}\""))?;
+
+    let warn_ignored = |key: serde_ignored::Path| {
+        warn!("ignoring unknown config key `{key}` in \"{path}\"");
+    };
+    let config = if path.ends_with(".json") {
+        // Read straight from the text, a `serde_json::Value` would keep only the last of the
+        // duplicate keys.
+        let mut deserializer = serde_json::Deserializer::from_str(&content);
+        let config: Config = serde_ignored::deserialize(&mut deserializer, warn_ignored)
+            .with_context(|| format!("Invalid config file \"{path}\""))?;
+        deserializer
+            .end()
+            .with_context(|| format!("Malformed config file \"{path}\""))?;
+        config
+    } else {
+        let value: toml::Value = toml::from_str(&content)
+            .with_context(|| format!("Malformed config file \"{path}\""))?;
+        serde_ignored::deserialize(value, warn_ignored)
+            .with_context(|| format!("Invalid config file \"{path}\""))?
+    };
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `content` to a temporary file named `name` and reads it as a config.
+    fn read(name: &str, content: &str) -> Result<Config> {
+        let path = std::env::temp_dir().join(format!("{}-{name}", std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        let config = read_config(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn valid_course_codes() {
+        for code in ["1372-1-1", "0011-2-3", "9999-0-9"] {
+            assert!(is_valid_course_code(code), "{code:?} rejected");
+        }
+    }
+
+    #[test]
+    fn malformed_course_codes_are_rejected() {
+        for code in [
+            "",
+            "1372",
+            "1372-1",
+            "137-1-1",
+            "13722-1-1",
+            "1372-11-1",
+            "1372-1-a",
+            "1372-1-1-1",
+            " 1372-1-1",
+            "CSE 1111",
+        ] {
+            assert!(!is_valid_course_code(code), "{code:?} accepted");
+        }
+    }
+
+    #[test]
+    fn duplicate_toml_keys_fail_reading() {
+        let content = r#"
+            [preferred_sections]
+            "1372-1-1" = ["A"]
+            "1372-1-1" = ["B"]
+        "#;
+        assert!(read("duplicate.toml", content).is_err());
+    }
+
+    #[test]
+    fn duplicate_json_keys_fail_reading() {
+        let content = r#"{"preferred_sections": {"1372-1-1": ["A"], "1372-1-1": ["B"]}}"#;
+        let e = read("duplicate.json", content).unwrap_err();
+        assert!(format!("{e:#}").contains("duplicate course code \"1372-1-1\""));
+    }
+
+    #[test]
+    fn problems_list_malformed_codes_and_empty_sections() {
+        let content = r#"
+            [preferred_sections]
+            "1372-1-1" = ["A"]
+            "2100-1-1" = []
+            "CSE 1111" = ["B"]
+
+            [match_mode]
+            "1372-1" = "exact"
+        "#;
+        let config = read("problems.toml", content).unwrap();
+        assert_eq!(
+            config_problems(&config),
+            [
+                "Malformed course code \"1372-1\", expected codes like \"1372-1-1\"",
+                "Malformed course code \"CSE 1111\", expected codes like \"1372-1-1\"",
+                "No preferred sections for course \"2100-1-1\"",
+            ]
+        );
+        assert!(validate_course_codes(&config).is_err());
+    }
+
+    #[test]
+    fn valid_config_has_no_problems() {
+        let content = r#"{"preferred_sections": {"1372-1-1": ["A", "B"]}}"#;
+        let config = read("valid.json", content).unwrap();
+        assert!(config_problems(&config).is_empty());
+        assert!(validate_course_codes(&config).is_ok());
+    }
+}
@@ -0,0 +1,55 @@
+use anyhow::{Context, Result};
+use keyring::Entry;
+
+const SERVICE: &str = "uiu-auto-section-selection";
+/// Account of the entry holding the student ID saved last, so later runs need no `--student-id`.
+const DEFAULT_ACCOUNT: &str = "default-student-id";
+
+fn password_entry(student_id: &str) -> keyring::Result<Entry> {
+    Entry::new(SERVICE, &format!("student:{student_id}"))
+}
+
+/// Saves the password of `student_id` to the OS keyring and makes it the default student.
+pub fn save(student_id: &str, password: &str) -> Result<()> {
+    password_entry(student_id)
+        .and_then(|entry| entry.set_password(password))
+        .context("Failed to save the password to the keyring")?;
+    Entry::new(SERVICE, DEFAULT_ACCOUNT)
+        .and_then(|entry| entry.set_password(student_id))
+        .context("Failed to save the student ID to the keyring")?;
+    Ok(())
+}
+
+/// The student ID saved last, if any.
+pub fn saved_student_id() -> Option<String> {
+    Entry::new(SERVICE, DEFAULT_ACCOUNT)
+        .and_then(|entry| entry.get_password())
+        .ok()
+}
+
+/// The saved password of `student_id`, if any. A keyring that can't be read counts as empty.
+pub fn saved_password(student_id: &str) -> Option<String> {
+    match password_entry(student_id).and_then(|entry| entry.get_password()) {
+        Ok(password) => Some(password),
+        Err(keyring::Error::NoEntry) => None,
+        Err(e) => {
+            tracing::debug!("Reading the keyring failed: {e}");
+            None
+        }
+    }
+}
+
+/// Removes the saved password of `student_id`, and the default student ID if it's this one.
+pub fn forget(student_id: &str) -> Result<()> {
+    let deleted = password_entry(student_id).and_then(|entry| entry.delete_credential());
+    match deleted {
+        Ok(()) | Err(keyring::Error::NoEntry) => {}
+        Err(e) => return Err(e).context("Failed to remove the password from the keyring"),
+    }
+    if saved_student_id().as_deref() == Some(student_id) {
+        Entry::new(SERVICE, DEFAULT_ACCOUNT)
+            .and_then(|entry| entry.delete_credential())
+            .context("Failed to remove the student ID from the keyring")?;
+    }
+    Ok(())
+}
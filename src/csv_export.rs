@@ -0,0 +1,38 @@
+use anyhow::{Context, Result};
+
+use crate::ucam_cloud_api::Section;
+
+/// Writes one row per section, the schedule flattened to `"<day> <times>"` pairs joined by `; `.
+pub fn write_sections_csv(path: &str, sections: &[Section]) -> Result<()> {
+    let mut writer = csv::Writer::from_path(path)
+        .with_context(|| format!("Failed to create CSV file \"{path}\""))?;
+    writer.write_record([
+        "section_name",
+        "faculty_name",
+        "total_seats",
+        "available_seats",
+        "waitlist_count",
+        "room_details",
+        "schedule",
+    ])?;
+    for section in sections {
+        let mut schedule: Vec<_> = section.schedule.iter().collect();
+        schedule.sort();
+        let schedule = schedule
+            .into_iter()
+            .map(|(day, times)| format!("{day} {times}"))
+            .collect::<Vec<_>>()
+            .join("; ");
+        writer.write_record([
+            section.section_name.as_str(),
+            section.faculty_name.as_str(),
+            &section.total_seats.to_string(),
+            &section.available_seats.to_string(),
+            &section.waitlist_count.to_string(),
+            section.room_details.as_str(),
+            &schedule,
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+
+/// How the events of a selection run are reported.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Readable logs and a summary table.
+    #[default]
+    Human,
+    /// One JSON object per line on stdout for every event, the logs go to stderr.
+    Ndjson,
+}
+
+/// Something worth reporting that happened to a course.
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// The sections of the course were fetched.
+    Poll {
+        selection_open: bool,
+        sections: Vec<SectionSeats<'a>>,
+    },
+    /// The taken seats of a section changed since the previous poll.
+    SeatChange {
+        section_name: &'a str,
+        seats_taken_before: usize,
+        seats_taken: usize,
+        total_seats: usize,
+    },
+    /// A select of the section is about to be posted.
+    SelectAttempt {
+        section_name: &'a str,
+        attempt: u32,
+        max_attempts: u32,
+    },
+    /// A posted select failed.
+    SelectFailed {
+        section_name: &'a str,
+        attempt: u32,
+        error: String,
+    },
+    Enrolled {
+        section_name: &'a str,
+    },
+    /// A waitlist join of the section is about to be posted.
+    WaitlistAttempt {
+        section_name: &'a str,
+        attempt: u32,
+        max_attempts: u32,
+    },
+    /// A posted waitlist join failed.
+    WaitlistFailed {
+        section_name: &'a str,
+        attempt: u32,
+        error: String,
+    },
+    /// The selection of the course is over.
+    Outcome {
+        outcome: String,
+        attempts: u32,
+    },
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SectionSeats<'a> {
+    pub section_name: &'a str,
+    pub seats_taken: usize,
+    pub total_seats: usize,
+    pub waitlist_count: usize,
+}
+
+#[derive(serde::Serialize)]
+struct Record<'a> {
+    timestamp: DateTime<Utc>,
+    course_code: &'a str,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+/// Where the events go, the human output already logs all of them so it drops them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EventSink {
+    format: OutputFormat,
+}
+
+impl EventSink {
+    pub fn new(format: OutputFormat) -> Self {
+        Self { format }
+    }
+
+    pub fn is_ndjson(&self) -> bool {
+        self.format == OutputFormat::Ndjson
+    }
+
+    pub fn emit(&self, course_code: &str, event: Event) {
+        if !self.is_ndjson() {
+            return;
+        }
+        let record = Record {
+            timestamp: Utc::now(),
+            course_code,
+            event: &event,
+        };
+        match serde_json::to_string(&record) {
+            Ok(line) => println!("{line}"),
+            Err(e) => tracing::warn!("Serializing the {event:?} event failed: {e}"),
+        }
+    }
+}
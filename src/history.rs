@@ -0,0 +1,168 @@
+use std::{sync::Mutex, time::Duration};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use rusqlite::{Connection, params};
+use tracing::warn;
+
+use crate::ucam_cloud_api::CourseSections;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS section_polls (
+    id INTEGER PRIMARY KEY,
+    polled_at TEXT NOT NULL,
+    course_code TEXT NOT NULL,
+    section_name TEXT NOT NULL,
+    seats_taken INTEGER NOT NULL,
+    total_seats INTEGER NOT NULL,
+    waitlist_count INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS select_attempts (
+    id INTEGER PRIMARY KEY,
+    attempted_at TEXT NOT NULL,
+    course_code TEXT NOT NULL,
+    section_name TEXT NOT NULL,
+    -- `accepted` or `failed`.
+    outcome TEXT NOT NULL,
+    error TEXT,
+    latency_ms INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS waitlist_attempts (
+    id INTEGER PRIMARY KEY,
+    attempted_at TEXT NOT NULL,
+    course_code TEXT NOT NULL,
+    section_name TEXT NOT NULL,
+    -- `accepted` or `failed`.
+    outcome TEXT NOT NULL,
+    error TEXT,
+    latency_ms INTEGER NOT NULL
+);
+";
+
+/// Every poll and select attempt of the selection runs, kept in a SQLite database.
+#[derive(Debug)]
+pub struct History {
+    connection: Mutex<Connection>,
+}
+
+impl History {
+    /// Opens the database at `path`, creating it and its tables if needed.
+    pub fn open(path: &str) -> Result<Self> {
+        let connection = Connection::open(path)
+            .with_context(|| format!("Failed to open the history database \"{path}\""))?;
+        connection
+            .execute_batch(SCHEMA)
+            .with_context(|| format!("Failed to create the tables of \"{path}\""))?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Records the seat counts of every polled section, a failed write is only warned about.
+    pub fn record_poll(&self, course_info: &CourseSections) {
+        if let Err(e) = self.insert_poll(course_info) {
+            warn!(
+                "Recording the poll of {} failed: {e}",
+                course_info.course_code
+            );
+        }
+    }
+
+    fn insert_poll(&self, course_info: &CourseSections) -> rusqlite::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        {
+            let mut insert = transaction.prepare_cached(
+                "INSERT INTO section_polls (polled_at, course_code, section_name, seats_taken, \
+                 total_seats, waitlist_count) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            let polled_at = Utc::now().to_rfc3339();
+            for s in &course_info.sections {
+                insert.execute(params![
+                    polled_at,
+                    course_info.course_code,
+                    s.section_name,
+                    s.seats_taken as i64,
+                    s.total_seats as i64,
+                    s.waitlist_count as i64,
+                ])?;
+            }
+        }
+        transaction.commit()
+    }
+
+    /// Records a posted select, failed when there's an `error`.
+    pub fn record_select(
+        &self,
+        course_code: &str,
+        section_name: &str,
+        error: Option<&str>,
+        latency: Duration,
+    ) {
+        let attempt = Attempt {
+            course_code,
+            section_name,
+            error,
+            latency,
+        };
+        if let Err(e) = self.insert_attempt("select_attempts", attempt) {
+            warn!("Recording the select of {course_code} failed: {e}");
+        }
+    }
+
+    /// Records a posted waitlist join, failed when there's an `error`.
+    pub fn record_waitlist_join(
+        &self,
+        course_code: &str,
+        section_name: &str,
+        error: Option<&str>,
+        latency: Duration,
+    ) {
+        let attempt = Attempt {
+            course_code,
+            section_name,
+            error,
+            latency,
+        };
+        if let Err(e) = self.insert_attempt("waitlist_attempts", attempt) {
+            warn!("Recording the waitlist join of {course_code} failed: {e}");
+        }
+    }
+
+    fn insert_attempt(&self, table: &str, attempt: Attempt) -> rusqlite::Result<()> {
+        let Attempt {
+            course_code,
+            section_name,
+            error,
+            latency,
+        } = attempt;
+        let outcome = if error.is_some() {
+            "failed"
+        } else {
+            "accepted"
+        };
+        self.connection.lock().unwrap().execute(
+            &format!(
+                "INSERT INTO {table} (attempted_at, course_code, section_name, outcome, error, \
+                 latency_ms) VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
+            ),
+            params![
+                Utc::now().to_rfc3339(),
+                course_code,
+                section_name,
+                outcome,
+                error,
+                latency.as_millis() as i64,
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+/// A posted select or waitlist join.
+struct Attempt<'a> {
+    course_code: &'a str,
+    section_name: &'a str,
+    error: Option<&'a str>,
+    latency: Duration,
+}
@@ -0,0 +1,61 @@
+use chrono::{Datelike, Local, NaiveDate, Utc};
+use tracing::warn;
+
+use crate::ucam_cloud_api::Section;
+
+/// Builds an iCalendar document with a weekly recurring event per class of the sections, starting
+/// from each class's next occurrence. Sections with an unparseable schedule are left out.
+pub fn sections_to_ical(sections: &[Section]) -> String {
+    let today = Local::now().date_naive();
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ");
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//uiu-auto-section-selection//EN".to_string(),
+    ];
+    for section in sections {
+        let slots = match section.class_slots() {
+            Ok(slots) => slots,
+            Err(e) => {
+                warn!(
+                    "leaving section {} out of the calendar: {e}",
+                    section.section_name
+                );
+                continue;
+            }
+        };
+        for (i, slot) in slots.iter().enumerate() {
+            let days_ahead =
+                (slot.day.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+            let date = today + chrono::Days::new(days_ahead as u64);
+            lines.extend([
+                "BEGIN:VEVENT".to_string(),
+                format!("UID:{}-{i}@uiu-auto-section-selection", section.id),
+                format!("DTSTAMP:{stamp}"),
+                format!("DTSTART:{}", local_timestamp(date, slot.start)),
+                format!("DTEND:{}", local_timestamp(date, slot.end)),
+                "RRULE:FREQ=WEEKLY".to_string(),
+                format!("SUMMARY:{}", escape_text(&section.section_name)),
+                format!("LOCATION:{}", escape_text(&section.room_details)),
+                format!("DESCRIPTION:{}", escape_text(&section.faculty_name)),
+                "END:VEVENT".to_string(),
+            ]);
+        }
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut ical = lines.join("\r\n");
+    ical.push_str("\r\n");
+    ical
+}
+
+fn local_timestamp(date: NaiveDate, time: chrono::NaiveTime) -> String {
+    date.and_time(time).format("%Y%m%dT%H%M%S").to_string()
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
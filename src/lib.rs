@@ -0,0 +1,22 @@
+//! Client for the Ucam cloud API of United International University, the part of the auto
+//! section selection app reusable by other tools.
+//!
+//! Start with [`ucam_cloud_api::ApiClient`], log in with [`ucam_cloud_api::ApiClient::login`] and
+//! every other request is authenticated with the returned token. [`selection::auto_select_section`]
+//! is the selection engine the app runs for every preadvised course.
+
+pub mod color;
+pub mod config;
+pub mod events;
+pub mod history;
+pub mod local_time;
+mod macros;
+pub mod metrics;
+pub mod notify;
+pub mod schedule;
+pub mod section_api;
+pub mod section_pattern;
+pub mod selection;
+pub mod strategy;
+pub mod ucam_cloud_api;
+pub mod watch;
@@ -0,0 +1,24 @@
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset, Local, Utc};
+
+static TIMEZONE: OnceLock<FixedOffset> = OnceLock::new();
+
+/// Shows every time at the UTC offset `offset`(eg. `"+06:00"`) instead of the system's timezone,
+/// only the first call takes effect.
+pub fn set_timezone(offset: &str) -> Result<()> {
+    let offset: FixedOffset = offset.parse().with_context(|| {
+        format!("Invalid timezone \"{offset}\", expected a UTC offset like \"+06:00\"")
+    })?;
+    _ = TIMEZONE.set(offset);
+    Ok(())
+}
+
+/// `dt` in the configured timezone, the system's one without it.
+pub fn to_local(dt: &DateTime<Utc>) -> DateTime<FixedOffset> {
+    match TIMEZONE.get() {
+        Some(offset) => dt.with_timezone(offset),
+        None => dt.with_timezone(&Local).fixed_offset(),
+    }
+}
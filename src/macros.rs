@@ -1,12 +1,23 @@
+/// Concatenates any number of `&'static str` constants at compile time, every concatenation is
+/// checked against its inputs while compiling so a broken one can never produce a garbage string.
 macro_rules! concat_sstr {
-    ($a:expr, $b:expr) => {{
+    ($a:expr $(,)?) => {{
+        const _: &str = $a;
+        $a
+    }};
+    ($a:expr, $b:expr $(,)?) => {{
         const _: &str = $a;
         const _: &str = $b;
 
         const LEN: usize = $a.len() + $b.len();
         const SLICE: [u8; LEN] = $crate::macros::concat_bytes::<LEN>($a.as_bytes(), $b.as_bytes());
-        unsafe { std::str::from_utf8_unchecked(&SLICE) }
+        const STR: &str = $crate::macros::str_from_utf8(&SLICE);
+        const _: () = assert!($crate::macros::is_concat_of(STR, $a, $b));
+        STR
     }};
+    ($a:expr, $b:expr, $($rest:expr),+ $(,)?) => {
+        $crate::macros::concat_sstr!($crate::macros::concat_sstr!($a, $b), $($rest),+)
+    };
 }
 pub(crate) use concat_sstr;
 
@@ -25,3 +36,76 @@ pub const fn concat_bytes<const LEN: usize>(a: &[u8], b: &[u8]) -> [u8; LEN] {
 
     return bytes;
 }
+
+pub const fn str_from_utf8(bytes: &[u8]) -> &str {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(_) => panic!("Concatenated string is not valid UTF-8"),
+    }
+}
+
+/// Whether `s` is exactly `a` followed by `b`.
+pub const fn is_concat_of(s: &str, a: &str, b: &str) -> bool {
+    let (s, a, b) = (s.as_bytes(), a.as_bytes(), b.as_bytes());
+    if s.len() != a.len() + b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if s[i] != a[i] {
+            return false;
+        }
+        i += 1;
+    }
+    let mut j = 0;
+    while j < b.len() {
+        if s[a.len() + j] != b[j] {
+            return false;
+        }
+        j += 1;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concat_bytes_of_empty_inputs() {
+        assert_eq!(concat_bytes::<0>(b"", b""), [0u8; 0]);
+        assert_eq!(&concat_bytes::<3>(b"", b"abc"), b"abc");
+        assert_eq!(&concat_bytes::<3>(b"abc", b""), b"abc");
+    }
+
+    #[test]
+    fn concat_bytes_of_one_byte_inputs() {
+        assert_eq!(&concat_bytes::<2>(b"a", b"b"), b"ab");
+        assert_eq!(&concat_bytes::<1>(b"/", b""), b"/");
+    }
+
+    #[test]
+    fn concat_bytes_keeps_multi_byte_utf8_whole() {
+        let (a, b) = ("ঢাকা", "/café");
+        const LEN: usize = "ঢাকা".len() + "/café".len();
+        let bytes = concat_bytes::<LEN>(a.as_bytes(), b.as_bytes());
+        assert_eq!(str_from_utf8(&bytes), "ঢাকা/café");
+    }
+
+    #[test]
+    fn concat_sstr_of_several_constants() {
+        const PATH: &str = concat_sstr!("/v3", "/auth", "/login");
+        assert_eq!(PATH, "/v3/auth/login");
+        assert_eq!(concat_sstr!("", "é"), "é");
+    }
+
+    #[test]
+    fn is_concat_of_checks_every_byte() {
+        assert!(is_concat_of("ab", "a", "b"));
+        assert!(is_concat_of("", "", ""));
+        assert!(is_concat_of("é", "", "é"));
+        assert!(!is_concat_of("ab", "b", "a"));
+        assert!(!is_concat_of("abc", "a", "b"));
+        assert!(!is_concat_of("a", "a", "b"));
+    }
+}
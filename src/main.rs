@@ -1,247 +1,1773 @@
-#![allow(unused)]
-
 use std::{
     collections::HashMap,
-    fmt::Debug,
-    hash::Hash,
-    io::{BufRead, Write},
+    io::{BufRead, IsTerminal, Write},
+    sync::{Arc, RwLock},
+    time::Duration,
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum, error::ErrorKind};
+use futures_util::{StreamExt, stream};
 use tokio::{self, fs};
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, error, info, warn};
+
+use uiu_auto_section_selection::section_api::SectionApi;
+use uiu_auto_section_selection::selection::{
+    HeldSchedules, MIN_POLL_INTERVAL, PreferredSections, SelectionOptions, SelectionOutcome,
+    Session, SharedState, auto_select_section, deadline_passed, sleep_unless_cancelled,
+};
+use uiu_auto_section_selection::ucam_cloud_api::{
+    ApiClient, ApiError, CircuitBreakerPolicy, CourseSection, Login, LoginRequest, PreadviceCourse,
+};
+use uiu_auto_section_selection::{
+    color, config, events, history, local_time, metrics, schedule, section_pattern, ucam_cloud_api,
+    watch,
+};
+
+use crate::color::Tone;
+use crate::config::Config;
+use crate::events::{Event, EventSink, OutputFormat};
+use crate::history::History;
+use crate::local_time::to_local;
+use crate::section_pattern::SectionPattern;
 
-use crate::ucam_cloud_api::{CourseSections, LoginRequest, SectionActionRequest};
+mod catalog;
+mod credentials;
+mod csv_export;
+mod ical;
+mod session;
+mod students;
+mod tui;
+
+/// Asks a yes or no question, an empty answer being yes. Assumes yes without asking unless
+/// `interactive`, fails instead of waiting for an answer that never comes when stdin isn't a
+/// terminal.
+fn prompt_yes(question: &str, interactive: bool) -> Result<bool> {
+    if !interactive {
+        info!("{question} Assuming yes, not running interactively.");
+        return Ok(true);
+    }
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!(
+            "Can't ask \"{question}\" as stdin isn't a terminal, pass --yes to assume yes"
+        );
+    }
+    let input = prompt_line(&format!("{question}(Y/n) "))?.to_lowercase();
+    Ok(matches!(input.as_str(), "" | "y" | "yes"))
+}
 
-mod macros;
-mod ucam_cloud_api;
+/// Prints `prompt` and reads a line from stdin, trimmed.
+fn prompt_line(prompt: &str) -> Result<String> {
+    print!("{prompt}");
+    std::io::stdout().flush()?;
 
-async fn check_for_dir_and_prompt_remove(path: &str) -> Result<bool> {
+    let mut handle = std::io::stdin().lock();
+    let mut buf = String::new();
+    handle.read_line(&mut buf)?;
+    Ok(buf.trim().to_string())
+}
+
+async fn check_for_dir_and_prompt_remove(path: &str, interactive: bool) -> Result<bool> {
     if fs::try_exists(path).await? {
-        print!("\"{path}\" already exists. Remove it?(Y/n) ");
-        std::io::stdout().flush()?;
+        let dir = fs::canonicalize(path).await?;
+        let working_dir = std::env::current_dir()?.canonicalize()?;
+        if dir == working_dir || !dir.starts_with(&working_dir) {
+            anyhow::bail!(
+                "Refusing to remove \"{path}\", it isn't inside the working directory \"{}\"",
+                working_dir.display()
+            );
+        }
+        if !prompt_yes(
+            &format!("\"{path}\" already exists. Remove it?"),
+            interactive,
+        )? {
+            return Ok(false);
+        }
+        fs::remove_dir_all(path).await?;
+        info!("Removed existing {path} directory.");
+    }
+    Ok(true)
+}
 
-        let mut handle = std::io::stdin().lock();
-        let mut buf = String::new();
-        handle.read_line(&mut buf)?;
+/// Reloads the preferred sections from the config at `path` on every SIGHUP, the course tasks
+/// pick them up before their next poll. An invalid config keeps the current ones.
+#[cfg(unix)]
+async fn reload_on_sighup(path: String, preferred_sections: PreferredSections) -> Result<()> {
+    let mut hangups = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+    while hangups.recv().await.is_some() {
+        let reloaded = config::load_config(&path)
+            .and_then(|config| section_pattern::compile_preferred_sections(&config));
+        match reloaded {
+            Ok(reloaded) => {
+                info!("SIGHUP received, reloaded the preferred sections from \"{path}\"");
+                let mut current = preferred_sections.write().unwrap();
+                for course_code in reloaded.keys() {
+                    if !current.contains_key(course_code) {
+                        warn!(
+                            "{course_code} - Newly configured, it's only selected after a restart"
+                        );
+                    }
+                }
+                *current = reloaded;
+            }
+            Err(e) => warn!(
+                "SIGHUP received but reloading \"{path}\" failed, keeping the current preferred sections: {e:#}"
+            ),
+        }
+    }
+    Ok(())
+}
 
-        let input = buf.trim().to_lowercase();
-        match input.as_str() {
-            "" | "y" | "yes" => {
-                fs::remove_dir_all(path).await?;
-                println!("Removed existing {path} directory.");
+/// Waits for Ctrl+C, or SIGTERM on unix, returning which one was received.
+async fn shutdown_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut terminate = signal(SignalKind::terminate()).ok();
+        let terminated = async {
+            match &mut terminate {
+                Some(terminate) => _ = terminate.recv().await,
+                None => std::future::pending().await,
             }
-            _ => return Ok(false),
+        };
+        tokio::select! {
+            Ok(()) = tokio::signal::ctrl_c() => "Ctrl+C",
+            () = terminated => "SIGTERM",
         }
     }
-    Ok(true)
+    #[cfg(not(unix))]
+    {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+        "Ctrl+C"
+    }
 }
 
-async fn auto_select_section(
-    client: reqwest::Client,
-    user_id: String,
+struct CourseReport {
     course_code: String,
-    preferred_sections: Vec<String>,
-) -> Result<()> {
+    /// Catalog id of the course, see [`ApiClient::fetch_course_data_as_student`]. Only looked up
+    /// with `check_selection_allowed`, as it takes fetching the catalog.
+    course_id: Option<String>,
+    course_name: String,
+    outcome: Result<SelectionOutcome>,
+    attempts: u32,
+    elapsed: Duration,
+}
+
+impl CourseReport {
+    /// Whether the course needs nothing more, enrolled in or left alone.
+    fn is_done(&self) -> bool {
+        matches!(
+            self.outcome,
+            Ok(SelectionOutcome::Enrolled { .. }
+                | SelectionOutcome::AlreadyEnrolled
+                | SelectionOutcome::Waitlisted { .. }
+                | SelectionOutcome::DryRun { .. }
+                | SelectionOutcome::Skipped)
+        )
+    }
+}
+
+fn print_summary(reports: &[CourseReport]) {
+    let code_width = reports
+        .iter()
+        .map(|r| r.course_code.len())
+        .max()
+        .unwrap_or(0)
+        .max("Course".len());
+    let name_width = reports
+        .iter()
+        .map(|r| r.course_name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("Name".len());
+    println!();
     println!(
-        "Started auto section selection for course {}, preferred sections: {:?}",
-        course_code, preferred_sections
+        "{:<code_width$}  {:<name_width$}  {:>8}  {:>9}  Outcome",
+        "Course", "Name", "Attempts", "Elapsed"
     );
-    loop {
-        let course_info =
-            ucam_cloud_api::fetch_course_sections(&client, &course_code, &user_id).await?;
-        if course_info.sections.is_empty() {
-            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    for report in reports {
+        let outcome = match &report.outcome {
+            Ok(outcome) => color::paint(&outcome.to_string(), outcome.tone()),
+            Err(e) => color::paint(&format!("Error: {e}"), Tone::Error),
+        };
+        println!(
+            "{:<code_width$}  {:<name_width$}  {:>8}  {:>9}  {outcome}",
+            report.course_code,
+            report.course_name,
+            report.attempts,
+            format!("{:.1?}", report.elapsed),
+        );
+    }
+}
+
+/// Exit code of a selection run finding no preadvised courses.
+const NO_PREADVISED_COURSES_EXIT_CODE: i32 = 3;
+
+const STUDENT_ID_ENV: &str = "UIU_STUDENT_ID";
+const PASSWORD_ENV: &str = "UIU_PASSWORD";
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Automatically selects the preferred sections on UIU Ucam cloud.",
+    after_help = "Credentials are read from the UIU_STUDENT_ID and UIU_PASSWORD environment variables \
+        when both are set, the command line arguments are only used otherwise, then the ones saved \
+        to the OS keyring by `login --save`. The password is prompted for, without echoing it, when it isn't given at all."
+)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Student ID used to log in, ignored when UIU_STUDENT_ID and UIU_PASSWORD are set.
+    #[arg(long, global = true)]
+    student_id: Option<String>,
+    /// Password used to log in, ignored when UIU_STUDENT_ID and UIU_PASSWORD are set.
+    #[arg(long, global = true)]
+    password: Option<String>,
+    /// Path to the config file with the preferred sections.
+    #[arg(long, global = true, default_value = config::DEFAULT_CONFIG_PATH)]
+    config: String,
+    /// Only log the section actions that would be sent, never post them.
+    #[arg(long, global = true)]
+    dry_run: bool,
+    /// Refuse to start if the config has any problem `validate --offline` finds, and give up on a
+    /// course whose preferred sections don't all exist instead of only warning.
+    #[arg(long)]
+    strict: bool,
+    /// Show a dashboard of the preadvised courses' sections to select from by hand instead of
+    /// selecting the preferred ones, nothing is logged meanwhile.
+    #[arg(long)]
+    tui: bool,
+    /// Only select the sections of these courses, eg. `1372-1-1,1393-1-1`, the other configured
+    /// ones are left alone.
+    #[arg(long, value_delimiter = ',')]
+    courses: Vec<String>,
+    /// Check again every this many seconds while there are no preadvised courses instead of
+    /// exiting with 3.
+    #[arg(long, value_name = "SECS")]
+    wait_for_preadvising: Option<u64>,
+    /// Select the sections of every student of this CSV, whose rows are
+    /// `student_id,password,config_path`, instead of a single student's.
+    #[arg(long, value_name = "CSV", conflicts_with_all = ["student_id", "password", "tui"])]
+    students: Option<String>,
+    /// Wait a random delay of up to this many milliseconds before each course's first select, so
+    /// the select doesn't look like a bot firing the instant the window opens.
+    #[arg(
+        long,
+        value_name = "MAX_MS",
+        num_args = 0..=1,
+        default_missing_value = "3000"
+    )]
+    humanize: Option<u64>,
+    /// Give up on every course not enrolled in by this time, eg. `2025-01-05T23:00:00+06:00`.
+    #[arg(long)]
+    deadline: Option<DateTime<Utc>>,
+    /// How the selection run reports what happens, `ndjson` prints every poll, seat change and
+    /// select attempt as a JSON object per line.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+    output: OutputFormat,
+    /// Run unattended: never prompt, assuming yes to any question, and write a PID file. A
+    /// selection run exits with 0 once every course is enrolled in, 2 if a selection window closed
+    /// or the deadline passed first and 1 otherwise.
+    #[arg(long, global = true)]
+    daemon: bool,
+    /// Assume yes to every question instead of asking, eg. whether to overwrite the dumped files.
+    #[arg(long, short, global = true)]
+    yes: bool,
+    /// Where `--daemon` writes its PID, removed again on exit.
+    #[arg(long, global = true, default_value = "./uiu-section.pid")]
+    pid_file: String,
+    /// Serve Prometheus metrics of the selection run on this address, eg. `127.0.0.1:9100`.
+    #[arg(long)]
+    metrics_addr: Option<std::net::SocketAddr>,
+    /// Record every poll's seat counts and every select attempt in this SQLite database, created
+    /// on first use.
+    #[arg(long)]
+    history_db: Option<String>,
+    /// Append the logs to this file instead of printing them.
+    #[arg(long, global = true)]
+    log_file: Option<String>,
+    /// Minimum level of the logged messages, eg. `debug` or `warn`, `RUST_LOG` style filter
+    /// directives work too.
+    #[arg(long, global = true, default_value = "info")]
+    log_level: String,
+    /// Only print the enrollment results and fatal errors, overriding `--log-level`.
+    #[arg(long, global = true)]
+    quiet: bool,
+    /// Positional form of `--student-id`, kept for backward compatibility.
+    #[arg(value_name = "STUDENT_ID", conflicts_with = "student_id")]
+    positional_student_id: Option<String>,
+    /// Positional form of `--password`, kept for backward compatibility.
+    #[arg(value_name = "PASSWORD", conflicts_with = "password")]
+    positional_password: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Writes the enrolled sections of the preadvised courses to an iCalendar file.
+    ExportIcal {
+        /// Path of the `.ics` file to write.
+        output: String,
+    },
+    /// Dumps every course and its sections to the current directory.
+    Dump {
+        /// Format of the student view sections files.
+        #[arg(long, value_enum, default_value_t = DumpFormat::Json)]
+        format: DumpFormat,
+        /// Hours `all-courses.json` is reused for before the catalog is fetched again.
+        #[arg(long, value_name = "HOURS", default_value_t = 24)]
+        catalog_ttl: u32,
+        /// Fetch the catalog again even if `all-courses.json` hasn't expired yet.
+        #[arg(long)]
+        refresh_catalog: bool,
+    },
+    /// Logs the seat counts of a course's sections whenever they change, without selecting any.
+    Watch {
+        /// Code of the course to watch, eg. `1372-1-1`.
+        course_code: String,
+        /// How often to poll the sections, at least 250ms.
+        #[arg(long, default_value_t = 5000)]
+        interval_ms: u64,
+    },
+    /// Prints the enrolled section of every preadvised course.
+    Status,
+    /// Prints every section of a course, without selecting any.
+    ListSections {
+        /// Code of the course, eg. `1372-1-1`.
+        course_code: String,
+    },
+    /// Lists a course's sections to pick one from and selects it after confirming, honors
+    /// `--dry-run`.
+    Pick {
+        /// Code of the course, eg. `1372-1-1`.
+        course_code: String,
+    },
+    /// Writes a config listing every preadvised course and its sections to the `--config` path,
+    /// only the preferred sections are left to fill in.
+    InitConfig,
+    /// Checks the `--config`, listing every problem and failing if there's any.
+    Validate {
+        /// Skip checking the preferred sections against the server's, which needs logging in.
+        #[arg(long)]
+        offline: bool,
+    },
+    /// Logs in, checking the credentials.
+    Login {
+        /// Save the student ID and password to the OS keyring, later runs use them when no
+        /// credentials are given.
+        #[arg(long)]
+        save: bool,
+    },
+    /// Logs out the saved session and removes it.
+    Logout {
+        /// Also remove the credentials saved to the OS keyring by `login --save`.
+        #[arg(long)]
+        forget: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum DumpFormat {
+    Json,
+    Csv,
+}
+
+fn credentials_from_env() -> Option<(String, String)> {
+    let student_id = std::env::var(STUDENT_ID_ENV)
+        .ok()
+        .filter(|v| !v.is_empty())?;
+    let password = std::env::var(PASSWORD_ENV).ok().filter(|v| !v.is_empty())?;
+    Some((student_id, password))
+}
+
+async fn refresh_or_login(
+    client: &ApiClient,
+    login: &Login,
+    login_req: &LoginRequest,
+) -> Result<Login> {
+    if login.refresh_token_expires_at > Utc::now() {
+        match client.refresh_access_token(&login.refresh_token).await {
+            Ok(login) => {
+                info!("Refreshed the access token.");
+                persist_session(&login_req.user_id, &login);
+                return Ok(login);
+            }
+            Err(e) => warn!("Refreshing the access token failed: {e}, logging in again..."),
+        }
+    }
+    let login = client.login(login_req).await?;
+    info!("Logged in successfully.");
+    persist_session(&login_req.user_id, &login);
+    Ok(login)
+}
+
+/// Reuses the login saved by an earlier run while it's still usable, logs in otherwise.
+async fn resume_or_login(client: &ApiClient, login_req: &LoginRequest) -> Result<Login> {
+    let saved =
+        session::session_path().and_then(|path| session::load_session(&path, &login_req.user_id));
+    let Some(saved) = saved else {
+        let login = client.login(login_req).await?;
+        info!("Logged in successfully.");
+        persist_session(&login_req.user_id, &login);
+        return Ok(login);
+    };
+    let margin = chrono::TimeDelta::seconds(ucam_cloud_api::TOKEN_EXPIRY_MARGIN_SECS);
+    if saved.access_token_expires_at - margin > Utc::now() {
+        client.set_token(&saved);
+        info!("Reusing the saved session.");
+        return Ok(saved);
+    }
+    refresh_or_login(client, &saved, login_req).await
+}
+
+/// Saves `login` for the next run, failing only costs that run a login.
+fn persist_session(user_id: &str, login: &Login) {
+    let Some(path) = session::session_path() else {
+        return;
+    };
+    if let Err(e) = session::save_session(&path, user_id, login) {
+        warn!("Saving the session failed: {e:#}");
+    }
+}
+
+/// Catalog id by course code, from the cached `dump` catalog if it hasn't expired. A catalog that
+/// can't be fetched is only warned about, the selection doesn't need it.
+async fn catalog_ids(client: &ApiClient) -> HashMap<String, String> {
+    let courses = match catalog::load_catalog(std::path::Path::new(CATALOG_PATH)) {
+        Some(courses) => courses,
+        None => match client.fetch_all_courses().await {
+            Ok(courses) => courses,
+            Err(e) => {
+                warn!(
+                    "Fetching the catalog failed: {e}, selecting without checking whether it's allowed"
+                );
+                return HashMap::new();
+            }
+        },
+    };
+    courses.into_iter().map(|c| (c.code, c.id)).collect()
+}
+
+/// The student a selection is run for.
+struct Student<'a> {
+    login: Login,
+    login_req: &'a LoginRequest,
+    /// The `--courses` to select only, every preadvised one if empty.
+    only_courses: &'a [String],
+}
+
+/// Selects the preferred sections of the preadvised `courses`, only the `only_courses` ones of the
+/// `student` unless it's empty, until every one is done with. Returns the report of every course
+/// and the preadvised courses neither configured nor enrolled in.
+async fn select_preadvised(
+    client: &ApiClient,
+    student: Student<'_>,
+    config: &Config,
+    options: &SelectionOptions,
+    courses: Vec<PreadviceCourse>,
+    shared: SharedState,
+) -> Result<(Vec<CourseReport>, Vec<String>)> {
+    let Student {
+        mut login,
+        login_req,
+        only_courses,
+    } = student;
+    let cancel = shared.cancel.clone();
+    for course_code in only_courses {
+        if !courses.iter().any(|c| c.course_code == *course_code) {
+            warn!("Course {course_code} of --courses isn't preadvised, ignoring it...");
+        }
+    }
+    let catalog_ids = if options.check_selection_allowed {
+        catalog_ids(client).await
+    } else {
+        HashMap::new()
+    };
+    let started = std::time::Instant::now();
+    let mut reports = Vec::new();
+    let mut pending = Vec::new();
+    // Preadvised courses neither configured nor enrolled in, likely forgotten in the config.
+    let mut unconfigured = Vec::new();
+    for course in courses {
+        // Other courses' sections already held must not clash with the ones about to be selected.
+        let sections = match client
+            .fetch_course_sections(&course.course_code, &login_req.user_id)
+            .await
+        {
+            Ok(sections) => sections.sections,
+            // Only the clash checks of the other courses miss its held section then.
+            Err(e) => {
+                warn!(
+                    "Fetching the sections of {} failed: {e}, not checking its held section for clashes...",
+                    course.course_code
+                );
+                Vec::new()
+            }
+        };
+        let enrolled = sections.into_iter().find(|s| s.is_enrolled);
+        let is_enrolled = enrolled.is_some();
+        if let Some(held) = enrolled {
+            shared
+                .held_schedules
+                .lock()
+                .unwrap()
+                .insert(course.course_code.clone(), held.schedule);
+        }
+        if !only_courses.is_empty() && !only_courses.contains(&course.course_code) {
             continue;
         }
-        if course_info.sections.iter().any(|s| {
-            s.is_enrolled
-                && preferred_sections.iter().any(|ps| {
-                    ps.to_ascii_lowercase()
-                        .contains(&s.section_name.to_ascii_lowercase())
-                })
-        }) {
-            println!(
-                "Already enrolled in course {}, skipping...",
-                course_info.course_name
+        let course_id = catalog_ids
+            .get(&course.course_code)
+            .or_else(|| catalog_ids.get(&course.formal_code))
+            .cloned();
+        if options.check_selection_allowed && course_id.is_none() {
+            warn!(
+                "Course {} isn't in the catalog, selecting it without checking whether it's allowed",
+                course.course_code
             );
-            return Ok(());
         }
-        let mut section_id = None;
-        for preferred in preferred_sections.iter() {
-            let preferred_lower = preferred.to_ascii_lowercase();
-            if let Some(section) = course_info.sections.iter().find(|s| {
-                s.section_name
-                    .to_ascii_lowercase()
-                    .contains(&preferred_lower)
-                    && s.seats_taken < s.total_seats
-            }) {
-                section_id = Some(section.section_id);
-                break;
-            }
-        }
-        let Some(section_id) = section_id else {
-            tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+        let has_preference = config
+            .preferred_sections
+            .get(&course.course_code)
+            .is_some_and(|sections| !sections.is_empty());
+        if !has_preference {
+            info!(
+                "No preferred sections specified for course {}, skipping...",
+                course.course_code
+            );
+            if !is_enrolled {
+                unconfigured.push(course.course_code.clone());
+            }
+            reports.push(CourseReport {
+                course_code: course.course_code,
+                course_id,
+                course_name: course.course_name,
+                outcome: Ok(SelectionOutcome::Skipped),
+                attempts: 0,
+                elapsed: Duration::ZERO,
+            });
             continue;
+        }
+        pending.push(CourseReport {
+            course_code: course.course_code,
+            course_id,
+            course_name: course.course_name,
+            outcome: Ok(SelectionOutcome::NoSeatsYet),
+            attempts: 0,
+            elapsed: Duration::ZERO,
+        });
+    }
+
+    loop {
+        let refresher = tokio::spawn(keep_token_fresh(
+            client.clone(),
+            login_req.user_id.clone(),
+            login.clone(),
+            cancel.clone(),
+        ));
+        let mut join_set = tokio::task::JoinSet::new();
+        for mut report in pending.drain(..) {
+            let session = Session {
+                client: client.clone(),
+                user_id: login_req.user_id.clone(),
+            };
+            let mut faculty = config
+                .faculty
+                .get(&report.course_code)
+                .cloned()
+                .unwrap_or_default();
+            faculty
+                .avoid_faculty
+                .extend(config.avoid_faculty.iter().cloned());
+            let options = match config.poll_interval_ms.get(&report.course_code) {
+                Some(&ms) => options
+                    .clone()
+                    .with_poll_interval(&report.course_code, Duration::from_millis(ms)),
+                None => options.clone(),
+            };
+            let shared = shared.clone();
+            let span = tracing::info_span!("course", code = %report.course_code);
+            join_set.spawn(
+                async move {
+                    let mut attempts = 0;
+                    report.outcome = auto_select_section(
+                        session,
+                        report.course_code.clone(),
+                        report.course_id.clone(),
+                        faculty,
+                        options,
+                        shared,
+                        &mut attempts,
+                    )
+                    .await;
+                    report.attempts += attempts;
+                    report.elapsed = started.elapsed();
+                    report
+                }
+                .instrument(span),
+            );
+        }
+        for report in join_set.join_all().await {
+            match &report.outcome {
+                Err(e) if matches!(e.downcast_ref(), Some(ApiError::InvalidToken)) => {
+                    pending.push(report);
+                }
+                Err(e) => {
+                    error!(course = %report.course_code, "Selection failed: {e:#}");
+                    reports.push(report);
+                }
+                Ok(_) => reports.push(report),
+            }
+        }
+        refresher.abort();
+        if pending.is_empty() {
+            break;
+        }
+        if cancel.is_cancelled() {
+            let deadline_reached = deadline_passed(options);
+            for mut report in pending.drain(..) {
+                report.outcome = Ok(if deadline_reached {
+                    SelectionOutcome::DeadlineReached
+                } else {
+                    SelectionOutcome::Cancelled
+                });
+                reports.push(report);
+            }
+            break;
+        }
+        info!("Restarting the process as the access token expired...");
+        login = refresh_or_login(client, &login, login_req).await?;
+    }
+    Ok((reports, unconfigured))
+}
+
+/// How many students of `--students` are selected for at once.
+const MAX_CONCURRENT_STUDENTS: usize = 4;
+
+/// Selects the sections of every student of the `--students` CSV at `path`, each with their own
+/// config, and prints a summary per student. Malformed rows are skipped and reported.
+async fn run_students(args: &Args, path: &str) -> Result<()> {
+    let (students, malformed) = students::read_students(path)?;
+    for problem in &malformed {
+        warn!("Skipping {problem} of \"{path}\"");
+    }
+    info!("Selecting the sections of {} students...", students.len());
+    let history = open_history(args)?;
+    let cancel = CancellationToken::new();
+    cancel_on_shutdown(&cancel, args.deadline);
+    let mut reports: Vec<_> = stream::iter(students)
+        .map(|student| {
+            let span = tracing::info_span!("student", id = %student.student_id);
+            let history = history.clone();
+            let cancel = cancel.child_token();
+            async move {
+                let result = select_for_student(args, &student, history, cancel).await;
+                if let Err(e) = &result {
+                    error!("Selection failed: {e:#}");
+                }
+                (student.student_id, result)
+            }
+            .instrument(span)
+        })
+        .buffer_unordered(MAX_CONCURRENT_STUDENTS)
+        .collect()
+        .await;
+    reports.sort_by(|(a, _), (b, _)| a.cmp(b));
+    print_student_summary(&reports, &malformed);
+    Ok(())
+}
+
+async fn select_for_student(
+    args: &Args,
+    student: &students::StudentRow,
+    history: Option<Arc<History>>,
+    cancel: CancellationToken,
+) -> Result<Vec<CourseReport>> {
+    let config = config::load_config(&student.config_path)?;
+    let client = build_client(Some(&config))?;
+    let login_req = LoginRequest {
+        user_id: student.student_id.clone(),
+        password: student.password.clone(),
+        logout_other_sessions: false,
+    };
+    check_server(&client).await?;
+    let login = client.login(&login_req).await?;
+    let options = selection_options(args, &config, history)?;
+    let section_patterns = section_pattern::compile_preferred_sections(&config)?;
+    let preadvised = client.fetch_preadvised_courses().await?;
+    if preadvised.courses.is_empty() {
+        anyhow::bail!("No preadvised courses found, has the advisor completed preadvising?");
+    }
+    let shared = SharedState {
+        held_schedules: HeldSchedules::default(),
+        preferred_sections: Arc::new(RwLock::new(section_patterns)),
+        cancel,
+        poll_permits: Arc::new(tokio::sync::Semaphore::new(options.max_concurrent_courses)),
+    };
+    let (reports, _) = select_preadvised(
+        &client,
+        Student {
+            login,
+            login_req: &login_req,
+            only_courses: &args.courses,
+        },
+        &config,
+        &options,
+        preadvised.courses,
+        shared,
+    )
+    .await?;
+    Ok(reports)
+}
+
+fn print_student_summary(reports: &[(String, Result<Vec<CourseReport>>)], malformed: &[String]) {
+    let id_width = reports
+        .iter()
+        .map(|(id, _)| id.len())
+        .max()
+        .unwrap_or(0)
+        .max("Student".len());
+    println!();
+    println!("{:<id_width$}  {:>8}  Outcome", "Student", "Enrolled");
+    for (id, result) in reports {
+        let (enrolled, outcome) = match result {
+            Ok(courses) => {
+                let enrolled = courses
+                    .iter()
+                    .filter(|r| {
+                        matches!(
+                            r.outcome,
+                            Ok(SelectionOutcome::Enrolled { .. }
+                                | SelectionOutcome::AlreadyEnrolled)
+                        )
+                    })
+                    .count();
+                let unfinished: Vec<_> = courses
+                    .iter()
+                    .filter(|r| !r.is_done())
+                    .map(|r| match &r.outcome {
+                        Ok(outcome) => format!("{}: {outcome}", r.course_code),
+                        Err(e) => format!("{}: Error: {e}", r.course_code),
+                    })
+                    .collect();
+                let outcome = if unfinished.is_empty() {
+                    color::paint("Done", Tone::Success)
+                } else {
+                    color::paint(&unfinished.join("; "), Tone::Pending)
+                };
+                (format!("{enrolled}/{}", courses.len()), outcome)
+            }
+            Err(e) => (
+                "-".to_string(),
+                color::paint(&format!("Error: {e}"), Tone::Error),
+            ),
         };
-        let action = SectionActionRequest {
-            parent_course_code: course_code.to_string(),
-            section_id: section_id,
-            action: "select".to_string(),
-        };
-        let result = ucam_cloud_api::post_course_action(&client, &course_code, &action).await;
+        println!("{id:<id_width$}  {enrolled:>8}  {outcome}");
+    }
+    for problem in malformed {
         println!(
-            "{} - Attempted to select section {}, result: {:?}",
-            course_info.course_name, section_id, result
+            "{}",
+            color::paint(&format!("Skipped {problem}"), Tone::Error)
         );
-        return Ok(());
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = std::env::args().collect::<Vec<String>>();
-    if args.len() != 3 {
-        println!(
-            "Usage: {} <student_id> <password> | cargo run -- <student_id> <password>",
-            args[0]
-        );
-        return Ok(());
+/// Cancels `cancel` on Ctrl+C or SIGTERM, and once the `deadline` passes if there's one.
+fn cancel_on_shutdown(cancel: &CancellationToken, deadline: Option<DateTime<Utc>>) {
+    if let Some(deadline) = deadline {
+        let cancel = cancel.clone();
+        tokio::spawn(async move {
+            let left = (deadline - Utc::now()).to_std().unwrap_or_default();
+            tokio::time::sleep(left).await;
+            info!("Reached the deadline {}, stopping...", to_local(&deadline));
+            cancel.cancel();
+        });
     }
-    let login_req = LoginRequest {
-        user_id: args[1].clone(),
-        password: args[2].clone(),
-        logout_other_sessions: false,
+    tokio::spawn({
+        let cancel = cancel.clone();
+        async move {
+            let signal = shutdown_signal().await;
+            info!("{signal} received, waiting for the in-flight requests to finish...");
+            cancel.cancel();
+        }
+    });
+}
+
+/// The selection options of `config` with the command line's overrides.
+fn selection_options(
+    args: &Args,
+    config: &Config,
+    history: Option<Arc<History>>,
+) -> Result<SelectionOptions> {
+    Ok(SelectionOptions {
+        dry_run: args.dry_run,
+        strict: args.strict,
+        quiet: args.quiet,
+        deadline: args.deadline,
+        humanize: args.humanize.map(Duration::from_millis),
+        events: EventSink::new(args.output),
+        history,
+        ..SelectionOptions::from_config(config)?
+    })
+}
+
+fn open_history(args: &Args) -> Result<Option<Arc<History>>> {
+    let history = args.history_db.as_deref().map(History::open).transpose()?;
+    Ok(history.map(Arc::new))
+}
+
+/// A client with the request settings of `config`, the default ones without it.
+fn build_client(config: Option<&Config>) -> Result<ApiClient> {
+    let mut client = ApiClient::new()?;
+    let Some(config) = config else {
+        return Ok(client);
     };
-    let preferred_sections = HashMap::from([
-        // (
-        //     "1312-1-1".to_string(),
-        //     vec!["D".to_string(), "Q".to_string()],
-        // ),
-        (
-            "1372-1-1".to_string(),
-            //vec!["K".to_string(), "B".to_string()],
-            vec!["B".to_string()],
-        ),
-        // (
-        //     "1373-1-1".to_string(),
-        //     vec!["K".to_string(), "B".to_string()],
-        // ),
-        // (
-        //     "1393-1-1".to_string(),
-        //     vec!["J".to_string(), "H".to_string()],
-        // ),
-    ]);
+    if let Some(timezone) = &config.timezone {
+        local_time::set_timezone(timezone)?;
+    }
+    client = client
+        .with_timeout(Duration::from_millis(config.request_timeout_ms))
+        .with_circuit_breaker(CircuitBreakerPolicy {
+            failure_threshold: config.circuit_breaker_failures,
+            cooldown: Duration::from_millis(config.circuit_breaker_cooldown_ms),
+        });
+    if let Some(user_agent) = &config.user_agent {
+        client = client.with_user_agent(user_agent)?;
+    }
+    if let Some(proxy) = &config.proxy {
+        client = client.with_proxy(proxy)?;
+    }
+    Ok(client)
+}
 
-    loop {
-        let client = ucam_cloud_api::login_client(&login_req).await?;
-        println!("Logged in successfully.");
+/// Fails fast when the server can't be reached, instead of every request failing on its own.
+async fn check_server(client: &ApiClient) -> Result<()> {
+    let latency = client.health_check().await.with_context(|| {
+        format!(
+            "The server at {} isn't answering, check the connection(and the proxy if one is \
+             configured)",
+            client.base_url()
+        )
+    })?;
+    tracing::debug!("The server answered in {}ms", latency.as_millis());
+    Ok(())
+}
 
-        let preadvised = ucam_cloud_api::fetch_preadvised_courses(&client).await?;
-        println!("Preadvised courses count: {}", preadvised.courses.len());
+/// Logs out the saved session of `student_id` if there's one and removes it, `forget` also
+/// removes the credentials saved to the keyring.
+async fn logout(client: &ApiClient, student_id: &str, forget: bool) -> Result<()> {
+    let path = session::session_path();
+    let saved = path
+        .as_deref()
+        .and_then(|path| session::load_session(path, student_id));
+    if let Some(saved) = saved {
+        client.set_token(&saved);
+        match client.logout().await {
+            Ok(()) => info!("Logged out."),
+            Err(e) => warn!("Logging out failed: {e}"),
+        }
+    }
+    if let Some(path) = &path {
+        session::clear_session(path)?;
+    }
+    if forget {
+        credentials::forget(student_id)?;
+        info!("Removed the saved credentials of {student_id} from the keyring.");
+    }
+    Ok(())
+}
 
-        let mut join_set = tokio::task::JoinSet::new();
-        for course in preadvised.courses {
-            let preferred_sections = preferred_sections
-                .get(&course.course_code)
-                .cloned()
-                .unwrap_or_default();
-            if preferred_sections.is_empty() {
-                println!(
-                    "No preferred sections specified for course {}, skipping...",
-                    course.course_code
-                );
-                continue;
-            }
-            join_set.spawn(auto_select_section(
-                client.clone(),
-                login_req.user_id.clone(),
-                course.course_code,
-                preferred_sections,
-            ));
+/// Refreshes the access token shared by every clone of `client` shortly before it expires, for
+/// as long as refreshing works. Once it doesn't the course tasks stop with
+/// [`ApiError::InvalidToken`] and get restarted after logging in again.
+async fn keep_token_fresh(
+    client: ApiClient,
+    user_id: String,
+    mut login: Login,
+    cancel: CancellationToken,
+) {
+    loop {
+        let refresh_at = login.access_token_expires_at
+            - chrono::TimeDelta::seconds(2 * ucam_cloud_api::TOKEN_EXPIRY_MARGIN_SECS);
+        let wait = (refresh_at - Utc::now()).to_std().unwrap_or_default();
+        sleep_unless_cancelled(wait, &cancel).await;
+        if cancel.is_cancelled() {
+            return;
         }
-        let res = join_set.join_all().await;
-        let mut restart = false;
-        for r in res {
-            if let Err(e) = r {
-                println!("Error in auto section selection task: {:?}", e);
-                restart |= format!("{e}").to_lowercase().contains("invalid token");
+        match client.refresh_access_token(&login.refresh_token).await {
+            Ok(refreshed) => {
+                info!("Refreshed the access token.");
+                persist_session(&user_id, &refreshed);
+                login = refreshed;
+            }
+            Err(e) => {
+                warn!("Refreshing the access token failed: {e}");
+                return;
             }
         }
-        if restart {
-            println!("Restarting the process due to invalid token...");
+    }
+}
+
+async fn export_ical(client: &ApiClient, output: &str) -> Result<()> {
+    let mut enrolled = Vec::new();
+    for course in client.fetch_all_courses().await? {
+        if !course.preadvised {
             continue;
         }
-        break;
+        let course_data = client.fetch_course_data_as_student(&course.id).await?;
+        enrolled.extend(
+            course_data
+                .sections
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|s| s.already_taken),
+        );
+    }
+    fs::write(output, ical::sections_to_ical(&enrolled)).await?;
+    info!("Wrote {} enrolled sections to {output}", enrolled.len());
+    Ok(())
+}
+
+async fn watch_seats(
+    client: &ApiClient,
+    mut login: Login,
+    login_req: &LoginRequest,
+    course_code: &str,
+    interval: Duration,
+) -> Result<()> {
+    if interval < MIN_POLL_INTERVAL {
+        anyhow::bail!(
+            "The watch interval must be at least {}ms",
+            MIN_POLL_INTERVAL.as_millis()
+        );
+    }
+    loop {
+        let result = watch::watch_course(
+            client,
+            course_code,
+            &login_req.user_id,
+            interval,
+            |previous, current| {
+                for change in watch::describe_seat_changes(previous, current) {
+                    info!("{} - {change}", current.course_name);
+                }
+            },
+        )
+        .await;
+        match result {
+            Err(ApiError::InvalidToken) => {
+                login = refresh_or_login(client, &login, login_req).await?;
+            }
+            result => return Ok(result?),
+        }
     }
+}
 
+async fn print_status(client: &ApiClient, student_id: &str) -> Result<()> {
+    let preadvised = client.fetch_preadvised_courses().await?;
+    let mut rows = Vec::new();
+    let mut enrolled_credits = 0;
+    for course in &preadvised.courses {
+        let course_info = client
+            .fetch_course_sections(&course.course_code, student_id)
+            .await?;
+        let enrolled = course_info.sections.iter().find(|s| s.is_enrolled);
+        if enrolled.is_some() {
+            enrolled_credits += course_info.credits;
+        }
+        let section = match enrolled {
+            Some(section) => {
+                color::paint(&format!("Section {}", section.section_name), Tone::Success)
+            }
+            None if course_info.selection_open => color::paint(
+                &format!(
+                    "not enrolled yet, selection closes at {}",
+                    to_local(&course_info.section_selection_end_time).format("%a %d %b %H:%M")
+                ),
+                Tone::Pending,
+            ),
+            None => color::paint(
+                &format!(
+                    "not enrolled yet, selection opens at {}",
+                    to_local(&course_info.section_selection_start_time).format("%a %d %b %H:%M")
+                ),
+                Tone::Pending,
+            ),
+        };
+        rows.push((course_info.course_code, course_info.course_name, section));
+    }
+    let code_width = rows
+        .iter()
+        .map(|(code, ..)| code.len())
+        .max()
+        .unwrap_or(0)
+        .max("Course".len());
+    let name_width = rows
+        .iter()
+        .map(|(_, name, _)| name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("Name".len());
+    println!(
+        "{:<code_width$}  {:<name_width$}  Enrollment",
+        "Course", "Name"
+    );
+    for (code, name, section) in rows {
+        println!("{code:<code_width$}  {name:<name_width$}  {section}");
+    }
+    println!(
+        "Enrolled credits: {enrolled_credits}/{}",
+        preadvised.total_credits
+    );
     Ok(())
 }
 
-async fn main2() -> Result<()> {
-    let args = std::env::args().collect::<Vec<String>>();
-    if args.len() != 3 {
+async fn list_sections(client: &ApiClient, course_code: &str, student_id: &str) -> Result<()> {
+    let mut course_info = client
+        .fetch_course_sections(course_code, student_id)
+        .await?;
+    course_info
+        .sections
+        .sort_by(|a, b| a.section_name.cmp(&b.section_name));
+    let rows: Vec<_> = course_info
+        .sections
+        .iter()
+        .map(|section| {
+            (
+                section.section_name.as_str(),
+                format!("{}/{}", section.seats_taken, section.total_seats),
+                section.faculty_name.as_str(),
+                format_schedule(section),
+            )
+        })
+        .collect();
+    let name_width = rows
+        .iter()
+        .map(|(name, ..)| name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("Section".len());
+    let faculty_width = rows
+        .iter()
+        .map(|(_, _, faculty, _)| faculty.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("Faculty".len());
+    println!("{} ({})", course_info.course_name, course_info.course_code);
+    println!(
+        "{:<name_width$}  {:>7}  {:<faculty_width$}  Schedule",
+        "Section", "Seats", "Faculty"
+    );
+    for (name, seats, faculty, schedule) in rows {
+        println!("{name:<name_width$}  {seats:>7}  {faculty:<faculty_width$}  {schedule}");
+    }
+    Ok(())
+}
+
+/// The class slots of a section, eg. `Sun 08:30-09:50, Tue 08:30-09:50`, or its raw schedule
+/// when it can't be parsed.
+fn format_schedule(section: &CourseSection) -> String {
+    match section.class_slots() {
+        Ok(slots) => slots
+            .iter()
+            .map(|slot| slot.to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+        Err(_) => format!("{:?}", section.schedule),
+    }
+}
+
+/// Lists a course's sections as a numbered menu and selects the one picked, after confirming when
+/// `confirm`, only logging it when `dry_run`.
+async fn pick_section(
+    client: &ApiClient,
+    course_code: &str,
+    student_id: &str,
+    confirm: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let mut course_info = client
+        .fetch_course_sections(course_code, student_id)
+        .await?;
+    if course_info.sections.is_empty() {
+        println!("{} has no sections yet.", course_info.course_name);
+        return Ok(());
+    }
+    course_info
+        .sections
+        .sort_by(|a, b| a.section_name.cmp(&b.section_name));
+    let name_width = course_info
+        .sections
+        .iter()
+        .map(|s| s.section_name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("Section".len());
+    let faculty_width = course_info
+        .sections
+        .iter()
+        .map(|s| s.faculty_name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("Faculty".len());
+    println!("{} ({})", course_info.course_name, course_info.course_code);
+    println!(
+        "{:>3}  {:<name_width$}  {:>7}  {:<faculty_width$}  Schedule",
+        "#", "Section", "Seats", "Faculty"
+    );
+    for (i, section) in course_info.sections.iter().enumerate() {
+        let seats = format!("{}/{}", section.seats_taken, section.total_seats);
         println!(
-            "Usage: {} <student_id> <password> | cargo run -- <student_id> <password>",
-            args[0]
+            "{:>3}  {:<name_width$}  {seats:>7}  {:<faculty_width$}  {}",
+            i + 1,
+            section.section_name,
+            section.faculty_name,
+            format_schedule(section)
+        );
+    }
+    let count = course_info.sections.len();
+    let section = loop {
+        let input = prompt_line(&format!("Section to select(1-{count}, empty to quit): "))?;
+        if input.is_empty() {
+            return Ok(());
+        }
+        match input.parse::<usize>() {
+            Ok(n) if (1..=count).contains(&n) => break &course_info.sections[n - 1],
+            _ => println!("\"{input}\" isn't a number from 1 to {count}."),
+        }
+    };
+    if section.is_enrolled {
+        println!("Already enrolled in section {}.", section.section_name);
+        return Ok(());
+    }
+    let note = if !section.is_selectable() {
+        " It isn't selectable right now."
+    } else if section.available_seats() == 0 {
+        " It has no free seat."
+    } else {
+        ""
+    };
+    let question = format!(
+        "Select section {} of {}?{note}",
+        section.section_name, course_info.course_name
+    );
+    if !prompt_yes(&question, confirm)? {
+        return Ok(());
+    }
+    if dry_run {
+        info!(
+            "Dry run, would have selected section {} (id {})",
+            section.section_name, section.section_id
         );
         return Ok(());
     }
+    client
+        .select_section(course_code, section.section_id)
+        .await?;
+    println!(
+        "Selected section {} of {}.",
+        section.section_name, course_info.course_name
+    );
+    Ok(())
+}
+
+/// Holds the PID file of a daemon run, removing it when dropped.
+struct PidFile(String);
+
+impl PidFile {
+    fn create(path: &str) -> Result<Self> {
+        std::fs::write(path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("Failed to write the PID file \"{path}\""))?;
+        Ok(Self(path.to_string()))
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// 0 once every course is enrolled in(or left alone), 2 if a selection window closed or the
+/// deadline passed first and 1 otherwise.
+fn daemon_exit_code(reports: &[CourseReport]) -> i32 {
+    let closed = |report: &CourseReport| {
+        matches!(
+            report.outcome,
+            Ok(SelectionOutcome::WindowClosed
+                | SelectionOutcome::NoSeatsYet
+                | SelectionOutcome::DeadlineReached)
+        )
+    };
+    if reports.iter().all(CourseReport::is_done) {
+        0
+    } else if reports.iter().any(closed) {
+        2
+    } else {
+        1
+    }
+}
+
+/// Logs every problem of the config at `path`, failing if there's any. Also checks that every
+/// preferred section exists when `server` is given, a course without published sections is skipped.
+async fn validate_config(path: &str, server: Option<(&ApiClient, &str)>) -> Result<()> {
+    let config = config::read_config(path)?;
+    let mut problems = config::config_problems(&config);
+    let mut courses: Vec<_> = config
+        .preferred_sections
+        .iter()
+        .filter(|(course_code, _)| config::is_valid_course_code(course_code))
+        .collect();
+    courses.sort_by_key(|(course_code, _)| *course_code);
+    for (course_code, preferred) in courses {
+        let mode = config
+            .match_mode
+            .get(course_code)
+            .copied()
+            .unwrap_or_default();
+        let mut patterns = Vec::new();
+        for preferred in preferred {
+            match SectionPattern::new(mode, preferred) {
+                Ok(pattern) => patterns.push(pattern),
+                Err(e) => problems.push(format!("{course_code}: {e:#}")),
+            }
+        }
+        let Some((client, student_id)) = server else {
+            continue;
+        };
+        let sections = match client.fetch_course_sections(course_code, student_id).await {
+            Ok(course_info) => course_info.sections,
+            Err(e) => {
+                problems.push(format!("{course_code}: Fetching the sections failed: {e}"));
+                continue;
+            }
+        };
+        if sections.is_empty() {
+            warn!("{course_code} - No sections published yet, can't check the preferred ones");
+            continue;
+        }
+        for pattern in patterns {
+            if !sections.iter().any(|s| pattern.matches(&s.section_name)) {
+                problems.push(format!(
+                    "{course_code}: The preferred section {pattern:?} matches none of the sections"
+                ));
+            }
+        }
+    }
+    if problems.is_empty() {
+        info!("No problems found in \"{path}\"");
+        return Ok(());
+    }
+    for problem in &problems {
+        error!("{problem}");
+    }
+    anyhow::bail!("Found {} problems in \"{path}\"", problems.len())
+}
+
+async fn init_config(
+    client: &ApiClient,
+    path: &str,
+    student_id: &str,
+    interactive: bool,
+) -> Result<()> {
+    if fs::try_exists(path).await?
+        && !prompt_yes(
+            &format!("\"{path}\" already exists. Overwrite it?"),
+            interactive,
+        )?
+    {
+        return Ok(());
+    }
+    let preadvised = client.fetch_preadvised_courses().await?;
+    let mut content = String::from(
+        "# Fill in the preferred sections of each course, most preferred first, a course left empty\n\
+         # is skipped.\n[preferred_sections]\n",
+    );
+    for course in &preadvised.courses {
+        let course_info = client
+            .fetch_course_sections(&course.course_code, student_id)
+            .await?;
+        let mut names: Vec<_> = course_info
+            .sections
+            .iter()
+            .map(|s| s.section_name.as_str())
+            .collect();
+        names.sort();
+        content.push_str(&format!(
+            "# {}, sections: {}\n\"{}\" = []\n",
+            course.course_name.trim(),
+            if names.is_empty() {
+                "none published yet".to_string()
+            } else {
+                names.join(", ")
+            },
+            course.course_code
+        ));
+    }
+    fs::write(path, content).await?;
+    info!(
+        "Wrote {} preadvised courses to {path}",
+        preadvised.courses.len()
+    );
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    // The logs would garble the dashboard.
+    let log_level = if args.tui {
+        "off"
+    } else if args.quiet {
+        "error"
+    } else {
+        &args.log_level
+    };
+    let log_filter = tracing_subscriber::EnvFilter::try_new(log_level)?;
+    match &args.log_file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open the log file \"{path}\""))?;
+            tracing_subscriber::fmt()
+                .with_env_filter(log_filter)
+                .with_ansi(false)
+                .with_writer(Arc::new(file))
+                .init();
+        }
+        // Keeps stdout to the events.
+        None if args.output == OutputFormat::Ndjson => tracing_subscriber::fmt()
+            .with_env_filter(log_filter)
+            .with_writer(std::io::stderr)
+            .init(),
+        None => tracing_subscriber::fmt().with_env_filter(log_filter).init(),
+    }
+    let pid_file = args
+        .daemon
+        .then(|| PidFile::create(&args.pid_file))
+        .transpose()?;
+    if let Some(path) = &args.students {
+        if args.command.is_some() {
+            anyhow::bail!("--students only works for the selection run, not for a subcommand");
+        }
+        return run_students(&args, path).await;
+    }
+    // The subcommands only use the config for the client settings, so they run without one.
+    let config = match config::load_config(&args.config) {
+        Ok(config) => Some(config),
+        Err(e) if args.command.is_some() => {
+            tracing::debug!("Running without a config: {e:#}");
+            None
+        }
+        Err(e) => return Err(e),
+    };
+    let client = build_client(config.as_ref())?;
+    if let Some(Command::Logout { forget }) = &args.command {
+        let student_id = std::env::var(STUDENT_ID_ENV)
+            .ok()
+            .filter(|v| !v.is_empty())
+            .or_else(|| args.student_id.clone())
+            .or_else(|| args.positional_student_id.clone())
+            .or_else(credentials::saved_student_id);
+        let Some(student_id) = student_id else {
+            Args::command()
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    "the student id is required",
+                )
+                .exit();
+        };
+        return logout(&client, &student_id, *forget).await;
+    }
+    if let Some(Command::Validate { offline: true }) = &args.command {
+        return validate_config(&args.config, None).await;
+    }
+    let (user_id, password) = match credentials_from_env() {
+        Some(credentials) => credentials,
+        None => {
+            let student_id = args
+                .student_id
+                .clone()
+                .or_else(|| args.positional_student_id.clone())
+                .or_else(credentials::saved_student_id);
+            let Some(user_id) = student_id else {
+                Args::command()
+                    .error(
+                        ErrorKind::MissingRequiredArgument,
+                        "the student id is required",
+                    )
+                    .exit();
+            };
+            let password = args
+                .password
+                .clone()
+                .or_else(|| args.positional_password.clone())
+                .or_else(|| credentials::saved_password(&user_id));
+            let password = match password {
+                Some(password) => password,
+                None if args.daemon => anyhow::bail!(
+                    "--daemon never prompts for the password, set UIU_PASSWORD or pass --password"
+                ),
+                None => rpassword::prompt_password(format!("Password for {user_id}: "))?,
+            };
+            (user_id, password)
+        }
+    };
     let login_req = LoginRequest {
-        user_id: args[1].clone(),
-        password: args[2].clone(),
+        user_id,
+        password,
         logout_other_sessions: false,
     };
+    if let Some(Command::Login { save }) = &args.command {
+        // Always logs in with the password, so a wrong one never gets saved.
+        let login = client.login(&login_req).await?;
+        info!("Logged in successfully.");
+        persist_session(&login_req.user_id, &login);
+        if *save {
+            credentials::save(&login_req.user_id, &login_req.password)?;
+            info!(
+                "Saved the credentials of {} to the keyring.",
+                login_req.user_id
+            );
+        }
+        return Ok(());
+    }
+    check_server(&client).await?;
+    let mut login = resume_or_login(&client, &login_req).await?;
+    let interactive = !args.daemon && !args.yes;
+
+    match &args.command {
+        Some(Command::ExportIcal { output }) => return export_ical(&client, output).await,
+        Some(Command::Dump {
+            format,
+            catalog_ttl,
+            refresh_catalog,
+        }) => {
+            let catalog_cache = CatalogCache {
+                ttl: chrono::Duration::hours(*catalog_ttl as i64),
+                refresh: *refresh_catalog,
+            };
+            return dump_catalog(
+                &client,
+                &login_req.user_id,
+                *format,
+                catalog_cache,
+                interactive,
+            )
+            .await;
+        }
+        Some(Command::Watch {
+            course_code,
+            interval_ms,
+        }) => {
+            let interval = Duration::from_millis(*interval_ms);
+            return watch_seats(&client, login, &login_req, course_code, interval).await;
+        }
+        Some(Command::Status) => return print_status(&client, &login_req.user_id).await,
+        Some(Command::ListSections { course_code }) => {
+            return list_sections(&client, course_code, &login_req.user_id).await;
+        }
+        Some(Command::Pick { course_code }) => {
+            if args.daemon {
+                anyhow::bail!("pick asks which section to select, it can't run with --daemon");
+            }
+            let confirm = !args.yes;
+            return pick_section(
+                &client,
+                course_code,
+                &login_req.user_id,
+                confirm,
+                args.dry_run,
+            )
+            .await;
+        }
+        Some(Command::InitConfig) => {
+            return init_config(&client, &args.config, &login_req.user_id, interactive).await;
+        }
+        Some(Command::Validate { .. }) => {
+            return validate_config(&args.config, Some((&client, &login_req.user_id))).await;
+        }
+        Some(Command::Login { .. } | Command::Logout { .. }) => {
+            unreachable!("handled before resuming the session")
+        }
+        None => {}
+    }
+
+    let config = config.expect("the selection run requires a config");
+    if args.strict {
+        let problems = config::config_problems(&config);
+        for problem in &problems {
+            error!("{problem}");
+        }
+        if !problems.is_empty() {
+            anyhow::bail!(
+                "Found {} problems in \"{}\", refusing to start with --strict",
+                problems.len(),
+                args.config
+            );
+        }
+    }
+    let options = selection_options(&args, &config, open_history(&args)?)?;
+    let section_patterns = section_pattern::compile_preferred_sections(&config)?;
+    if let Some(deadline) = options.deadline.filter(|&deadline| deadline <= Utc::now()) {
+        anyhow::bail!("The deadline {} already passed", to_local(&deadline));
+    }
 
-    let client = ucam_cloud_api::login_client(&login_req).await?;
-    println!("Logged in successfully.");
-    let all_courses = ucam_cloud_api::fetch_all_courses(&client).await?;
-    print!("Total courses fetched: {}\n", all_courses.len());
-    fs::write(
-        "all-courses.json",
-        serde_json::to_string_pretty(&all_courses)?.as_bytes(),
+    let mut preadvised = client.fetch_preadvised_courses().await?;
+    // Preadvising sometimes lands right before the selection window opens.
+    while preadvised.courses.is_empty() {
+        let Some(retry_secs) = args.wait_for_preadvising else {
+            error!(
+                "No preadvised courses found, has your advisor completed preadvising? Pass \
+                 --wait-for-preadvising <secs> to keep checking."
+            );
+            drop(pid_file);
+            std::process::exit(NO_PREADVISED_COURSES_EXIT_CODE);
+        };
+        warn!("No preadvised courses found yet, checking again in {retry_secs}s...");
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(retry_secs)) => {}
+            signal = shutdown_signal() => {
+                info!("{signal} received, stopping...");
+                return Ok(());
+            }
+        }
+        if client.token().is_none_or(|token| token.expires_soon()) {
+            login = refresh_or_login(&client, &login, &login_req).await?;
+        }
+        preadvised = client.fetch_preadvised_courses().await?;
+    }
+    info!("Preadvised courses count: {}", preadvised.courses.len());
+
+    if args.tui {
+        let cancel = CancellationToken::new();
+        let refresher = tokio::spawn(keep_token_fresh(
+            client.clone(),
+            login_req.user_id.clone(),
+            login.clone(),
+            cancel.clone(),
+        ));
+        let courses = preadvised
+            .courses
+            .into_iter()
+            .map(|course| (course.course_code, course.course_name))
+            .collect();
+        let result = tui::run(
+            client.clone(),
+            login_req.user_id.clone(),
+            courses,
+            options.waiting_poll_interval,
+            options.dry_run,
+        )
+        .await;
+        cancel.cancel();
+        refresher.abort();
+        return result;
+    }
+
+    if let Some(addr) = args.metrics_addr {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to serve the metrics on {addr}"))?;
+        info!("Serving the metrics on http://{addr}/metrics");
+        tokio::spawn(metrics::serve(listener));
+    }
+
+    let cancel = CancellationToken::new();
+    let shared = SharedState {
+        held_schedules: HeldSchedules::default(),
+        preferred_sections: Arc::new(RwLock::new(section_patterns)),
+        cancel: cancel.clone(),
+        poll_permits: Arc::new(tokio::sync::Semaphore::new(options.max_concurrent_courses)),
+    };
+    #[cfg(unix)]
+    tokio::spawn(reload_on_sighup(
+        args.config.clone(),
+        shared.preferred_sections.clone(),
+    ));
+    cancel_on_shutdown(&cancel, options.deadline);
+    let (mut reports, unconfigured) = select_preadvised(
+        &client,
+        Student {
+            login,
+            login_req: &login_req,
+            only_courses: &args.courses,
+        },
+        &config,
+        &options,
+        preadvised.courses,
+        shared,
     )
     .await?;
+    reports.sort_by(|a, b| a.course_code.cmp(&b.course_code));
+    if options.events.is_ndjson() {
+        for report in &reports {
+            let outcome = match &report.outcome {
+                Ok(outcome) => outcome.to_string(),
+                Err(e) => format!("Error: {e}"),
+            };
+            let attempts = report.attempts;
+            let event = Event::Outcome { outcome, attempts };
+            options.events.emit(&report.course_code, event);
+        }
+    } else {
+        print_summary(&reports);
+    }
+    if !unconfigured.is_empty() {
+        warn!(
+            "{} preadvised course{} not configured: {}",
+            unconfigured.len(),
+            if unconfigured.len() == 1 {
+                " was"
+            } else {
+                "s were"
+            },
+            unconfigured.join(", ")
+        );
+    }
+    if config.logout_on_exit {
+        match client.logout().await {
+            Ok(()) => {
+                info!("Logged out.");
+                let cleared =
+                    session::session_path().map_or(Ok(()), |path| session::clear_session(&path));
+                if let Err(e) = cleared {
+                    warn!("Removing the saved session failed: {e:#}");
+                }
+            }
+            Err(e) => warn!("Logging out failed: {e}"),
+        }
+    }
+    if args.daemon {
+        drop(pid_file);
+        // Stopping it on purpose isn't a failure.
+        let code = if cancel.is_cancelled() && !deadline_passed(&options) {
+            0
+        } else {
+            daemon_exit_code(&reports)
+        };
+        std::process::exit(code);
+    }
+
+    Ok(())
+}
+
+/// How many courses `dump` fetches at once.
+const DUMP_CONCURRENCY: usize = 8;
+
+const CATALOG_PATH: &str = "all-courses.json";
+
+/// How `dump` reuses the catalog cached in [`CATALOG_PATH`].
+#[derive(Debug, Clone, Copy)]
+struct CatalogCache {
+    ttl: chrono::Duration,
+    /// Fetch the catalog even if the cached one hasn't expired.
+    refresh: bool,
+}
+
+async fn dump_catalog(
+    client: &ApiClient,
+    user_id: &str,
+    format: DumpFormat,
+    catalog_cache: CatalogCache,
+    interactive: bool,
+) -> Result<()> {
+    let catalog_path = std::path::Path::new(CATALOG_PATH);
+    let cached = (!catalog_cache.refresh)
+        .then(|| catalog::load_catalog(catalog_path))
+        .flatten();
+    let all_courses = match cached {
+        Some(all_courses) => {
+            info!(
+                "Reusing the {} courses cached in {CATALOG_PATH}, --refresh-catalog fetches them again",
+                all_courses.len()
+            );
+            all_courses
+        }
+        None => {
+            let all_courses = client.fetch_all_courses().await?;
+            info!("Total courses fetched: {}", all_courses.len());
+            catalog::save_catalog(catalog_path, &all_courses, catalog_cache.ttl)?;
+            all_courses
+        }
+    };
 
-    const SECTIONS_DIR: &'static str = "sections";
-    if !check_for_dir_and_prompt_remove(SECTIONS_DIR).await? {
-        println!("Aborting...");
+    const SECTIONS_DIR: &str = "sections";
+    if !check_for_dir_and_prompt_remove(SECTIONS_DIR, interactive).await? {
+        info!("Aborting...");
         return Ok(());
     }
     fs::create_dir(SECTIONS_DIR).await?;
-    for course in all_courses.iter() {
-        let file_path = format!("{}/{}.json", SECTIONS_DIR, course.id);
-        let sections_data =
-            ucam_cloud_api::fetch_course_sections(&client, &course.id, &login_req.user_id).await?;
-        let sections = sections_data.sections;
-        let content = serde_json::to_string_pretty(&sections)?;
-        fs::write(&file_path, content).await?;
-        println!(
-            "Wrote sections({}) for course {} to {}",
-            sections.len(),
-            course.code,
-            file_path
-        );
+    let mut written = stream::iter(&all_courses)
+        .map(|course| async move {
+            let file_path = format!("{}/{}.json", SECTIONS_DIR, course.id);
+            let sections_data = client.fetch_course_sections(&course.id, user_id).await?;
+            let sections = sections_data.sections;
+            let content = serde_json::to_string_pretty(&sections)?;
+            fs::write(&file_path, content).await?;
+            info!(
+                "Wrote sections({}) for course {} to {}",
+                sections.len(),
+                course.code,
+                file_path
+            );
+            anyhow::Ok(())
+        })
+        .buffer_unordered(DUMP_CONCURRENCY);
+    while let Some(result) = written.next().await {
+        result?;
     }
+    info!(
+        "Wrote the sections of {} courses to {SECTIONS_DIR}",
+        all_courses.len()
+    );
 
-    const SECTIONS_STUDENT_VIEW_DIR: &'static str = "sections_student_view";
-    if !check_for_dir_and_prompt_remove(SECTIONS_STUDENT_VIEW_DIR).await? {
-        println!("Aborting...");
+    const SECTIONS_STUDENT_VIEW_DIR: &str = "sections_student_view";
+    if !check_for_dir_and_prompt_remove(SECTIONS_STUDENT_VIEW_DIR, interactive).await? {
+        info!("Aborting...");
         return Ok(());
     }
     fs::create_dir(SECTIONS_STUDENT_VIEW_DIR).await?;
-    for course in all_courses.iter() {
-        let file_path = format!("{}/{}.json", SECTIONS_STUDENT_VIEW_DIR, course.id);
-        let course_data = ucam_cloud_api::fetch_course_data_as_student(&client, &course.id).await?;
-        let content = serde_json::to_string_pretty(&course_data)?;
-        fs::write(&file_path, content).await?;
-        let sections_count = match &course_data.sections {
-            Some(sections) => sections.len(),
-            None => 0,
-        };
-        println!(
-            "Wrote Course data for course {} to {}, sections count: {}",
-            course.code, file_path, sections_count,
-        );
+    let mut written = stream::iter(&all_courses)
+        .map(|course| async move {
+            let course_data = client.fetch_course_data_as_student(&course.id).await?;
+            let file_path = match format {
+                DumpFormat::Json => {
+                    let file_path = format!("{}/{}.json", SECTIONS_STUDENT_VIEW_DIR, course.id);
+                    let content = serde_json::to_string_pretty(&course_data)?;
+                    fs::write(&file_path, content).await?;
+                    file_path
+                }
+                DumpFormat::Csv => {
+                    let file_path = format!("{}/{}.csv", SECTIONS_STUDENT_VIEW_DIR, course.id);
+                    let sections = course_data.sections.as_deref().unwrap_or_default();
+                    csv_export::write_sections_csv(&file_path, sections)?;
+                    file_path
+                }
+            };
+            let sections_count = match &course_data.sections {
+                Some(sections) => sections.len(),
+                None => 0,
+            };
+            info!(
+                "Wrote Course data for course {} to {}, sections count: {}",
+                course.code, file_path, sections_count,
+            );
+            anyhow::Ok(())
+        })
+        .buffer_unordered(DUMP_CONCURRENCY);
+    while let Some(result) = written.next().await {
+        result?;
     }
+    info!(
+        "Wrote the course data of {} courses to {SECTIONS_STUDENT_VIEW_DIR}",
+        all_courses.len()
+    );
     Ok(())
 }
@@ -0,0 +1,184 @@
+use std::{
+    fmt::Write,
+    future::Future,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use tracing::warn;
+
+pub static FETCH_TOTAL: Counter = Counter::new("fetch_total", "Course sections fetches.");
+pub static FETCH_ERRORS: Counter =
+    Counter::new("fetch_errors", "Course sections fetches that failed.");
+pub static SELECT_ATTEMPTS: Counter = Counter::new(
+    "select_attempts",
+    "Section selects posted, racing ones count once.",
+);
+pub static SELECT_SUCCESS: Counter =
+    Counter::new("select_success", "Section selects accepted by the server.");
+pub static WAITLIST_ATTEMPTS: Counter = Counter::new("waitlist_attempts", "Waitlist joins posted.");
+pub static WAITLIST_SUCCESS: Counter =
+    Counter::new("waitlist_success", "Waitlist joins accepted by the server.");
+pub static REQUEST_DURATION: Histogram = Histogram::new(
+    "request_duration_seconds",
+    "Latency of the fetches and selects.",
+);
+
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    value: AtomicU64,
+}
+
+impl Counter {
+    const fn new(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            value: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inc(&self) {
+        self.value.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String) {
+        let (name, help) = (self.name, self.help);
+        _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} counter");
+        _ = writeln!(out, "{name} {}", self.value.load(Ordering::Relaxed));
+    }
+}
+
+/// Upper bounds of the latency buckets, in seconds.
+const BUCKETS: [f64; 10] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 15.0, 30.0];
+
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    /// Observations at most as long as the bucket's bound, the ones longer than every bound only
+    /// count towards `count`.
+    buckets: [AtomicU64; BUCKETS.len()],
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    const fn new(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            buckets: [const { AtomicU64::new(0) }; BUCKETS.len()],
+            sum_micros: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter().zip(BUCKETS) {
+            if secs <= bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String) {
+        let (name, help) = (self.name, self.help);
+        let count = self.count.load(Ordering::Relaxed);
+        _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} histogram");
+        for (bucket, bound) in self.buckets.iter().zip(BUCKETS) {
+            let value = bucket.load(Ordering::Relaxed);
+            _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {value}");
+        }
+        _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}");
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        _ = writeln!(out, "{name}_sum {sum}\n{name}_count {count}");
+    }
+}
+
+/// Counts and times a fetch of the course sections.
+pub async fn track_fetch<T, E>(fetch: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+    FETCH_TOTAL.inc();
+    let started = Instant::now();
+    let result = fetch.await;
+    REQUEST_DURATION.observe(started.elapsed());
+    if result.is_err() {
+        FETCH_ERRORS.inc();
+    }
+    result
+}
+
+/// Counts and times a posted select.
+pub async fn track_select<T, E>(select: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+    SELECT_ATTEMPTS.inc();
+    let started = Instant::now();
+    let result = select.await;
+    REQUEST_DURATION.observe(started.elapsed());
+    if result.is_ok() {
+        SELECT_SUCCESS.inc();
+    }
+    result
+}
+
+/// Counts and times a posted waitlist join.
+pub async fn track_waitlist<T, E>(join: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+    WAITLIST_ATTEMPTS.inc();
+    let started = Instant::now();
+    let result = join.await;
+    REQUEST_DURATION.observe(started.elapsed());
+    if result.is_ok() {
+        WAITLIST_SUCCESS.inc();
+    }
+    result
+}
+
+/// Every metric in the Prometheus text format.
+pub fn render() -> String {
+    let mut out = String::new();
+    for counter in [
+        &FETCH_TOTAL,
+        &FETCH_ERRORS,
+        &SELECT_ATTEMPTS,
+        &SELECT_SUCCESS,
+        &WAITLIST_ATTEMPTS,
+        &WAITLIST_SUCCESS,
+    ] {
+        counter.render(&mut out);
+    }
+    REQUEST_DURATION.render(&mut out);
+    out
+}
+
+/// Answers every connection to `listener` with the metrics, whatever it asked for.
+pub async fn serve(listener: TcpListener) {
+    loop {
+        let mut stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                warn!("Accepting a metrics connection failed: {e}");
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        tokio::spawn(async move {
+            // Only read so the client doesn't see the connection reset.
+            let mut request = [0; 1024];
+            _ = stream.read(&mut request).await;
+            let body = render();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            _ = stream.write_all(response.as_bytes()).await;
+        });
+    }
+}
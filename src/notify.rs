@@ -0,0 +1,81 @@
+use std::{sync::LazyLock, time::Duration};
+
+use anyhow::{Context, Result};
+use tracing::warn;
+
+use crate::config::{Config, TelegramConfig};
+
+/// A notification taking longer than this is given up on.
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Shared by every notification so they reuse its connection pool.
+static HTTP: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(NOTIFY_TIMEOUT)
+        .build()
+        .expect("failed to build the notification HTTP client")
+});
+
+/// Posts `message` to a Discord channel through its webhook.
+pub async fn send_discord(webhook_url: &str, message: &str) -> Result<()> {
+    // Webhook URLs embed their secret token as well.
+    HTTP.post(webhook_url)
+        .json(&serde_json::json!({ "content": message }))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.without_url())
+        .context("Discord webhook request failed")?;
+    Ok(())
+}
+
+/// Sends `message` to a Telegram chat through the Bot API.
+pub async fn send_telegram(bot_token: &str, chat_id: &str, message: &str) -> Result<()> {
+    // The bot token is part of the URL, which reqwest errors would print otherwise.
+    let url = format!("https://api.telegram.org/bot{bot_token}/sendMessage");
+    HTTP.post(url)
+        .json(&serde_json::json!({ "chat_id": chat_id, "text": message }))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.without_url())
+        .context("Telegram sendMessage request failed")?;
+    Ok(())
+}
+
+/// Dispatches notifications to every configured channel.
+#[derive(Debug, Clone, Default)]
+pub struct Notifier {
+    discord_webhook_url: Option<String>,
+    telegram: Option<TelegramConfig>,
+}
+
+impl Notifier {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            discord_webhook_url: config.discord_webhook_url.clone(),
+            telegram: config.telegram.clone(),
+        }
+    }
+
+    /// Sends `message` to every configured channel, a failing channel is only logged so it never
+    /// gets in the way of the selection.
+    pub async fn notify(&self, message: &str) {
+        if let Some(webhook_url) = &self.discord_webhook_url {
+            if let Err(e) = send_discord(webhook_url, message).await {
+                warn!("Discord notification failed: {e}");
+            }
+        }
+        if let Some(telegram) = &self.telegram {
+            if let Err(e) = send_telegram(&telegram.bot_token, &telegram.chat_id, message).await {
+                warn!("Telegram notification failed: {e}");
+            }
+        }
+    }
+
+    /// Sends `message` like [`Notifier::notify`] without waiting for it.
+    pub fn notify_in_background(&self, message: String) {
+        let notifier = self.clone();
+        tokio::spawn(async move { notifier.notify(&message).await });
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result, bail};
+use chrono::{NaiveTime, Weekday};
+
+/// A weekly class of a section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClassSlot {
+    pub day: Weekday,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl ClassSlot {
+    pub fn overlaps(&self, other: &ClassSlot) -> bool {
+        self.day == other.day && self.start < other.end && other.start < self.end
+    }
+}
+
+impl std::fmt::Display for ClassSlot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}-{}",
+            self.day,
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+/// Parses a raw schedule mapping a day (eg. `"Sunday"` or `"Sun"`) to its time ranges (eg.
+/// `"08:30-09:50"`), several ranges of a day separated by `,` or `;`.
+pub fn parse_schedule(raw: &HashMap<String, String>) -> Result<Vec<ClassSlot>> {
+    let mut slots = Vec::new();
+    for (day, times) in raw {
+        let day = day
+            .trim()
+            .parse::<Weekday>()
+            .with_context(|| format!("Invalid schedule day \"{day}\""))?;
+        for range in times.split([',', ';']).filter(|r| !r.trim().is_empty()) {
+            let Some((start, end)) = range.split_once('-') else {
+                bail!("Invalid schedule time range \"{range}\"");
+            };
+            let (start, end) = (parse_time(start)?, parse_time(end)?);
+            if start >= end {
+                bail!("Schedule time range \"{range}\" ends before it starts");
+            }
+            slots.push(ClassSlot { day, start, end });
+        }
+    }
+    slots.sort_by_key(|s| (s.day.num_days_from_sunday(), s.start));
+    Ok(slots)
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime> {
+    let time = time.trim();
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(time, "%I:%M %p"))
+        .with_context(|| format!("Invalid schedule time \"{time}\""))
+}
+
+/// Whether any class of schedule `a` overlaps a class of schedule `b`, a schedule that can't be
+/// parsed never conflicts.
+pub fn schedules_conflict(a: &HashMap<String, String>, b: &HashMap<String, String>) -> bool {
+    let (Ok(a), Ok(b)) = (parse_schedule(a), parse_schedule(b)) else {
+        return false;
+    };
+    a.iter()
+        .any(|slot_a| b.iter().any(|slot_b| slot_a.overlaps(slot_b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(day, times)| (day.to_string(), times.to_string()))
+            .collect()
+    }
+
+    fn slot(day: Weekday, start: (u32, u32), end: (u32, u32)) -> ClassSlot {
+        let time = |(hour, minute)| NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+        ClassSlot {
+            day,
+            start: time(start),
+            end: time(end),
+        }
+    }
+
+    #[test]
+    fn parses_a_24_hour_range() {
+        let slots = parse_schedule(&schedule(&[("Sunday", "08:30-09:50")])).unwrap();
+        assert_eq!(slots, [slot(Weekday::Sun, (8, 30), (9, 50))]);
+    }
+
+    #[test]
+    fn parses_12_hour_times() {
+        let slots = parse_schedule(&schedule(&[("Tue", "11:00 AM-12:20 PM")])).unwrap();
+        assert_eq!(slots, [slot(Weekday::Tue, (11, 0), (12, 20))]);
+    }
+
+    #[test]
+    fn parses_several_ranges_of_a_day_in_order() {
+        let raw = schedule(&[
+            ("Wednesday", "14:00-15:20; 08:30-09:50"),
+            ("Saturday", "11:00-12:20, 12:30-13:50"),
+        ]);
+        assert_eq!(
+            parse_schedule(&raw).unwrap(),
+            [
+                slot(Weekday::Wed, (8, 30), (9, 50)),
+                slot(Weekday::Wed, (14, 0), (15, 20)),
+                slot(Weekday::Sat, (11, 0), (12, 20)),
+                slot(Weekday::Sat, (12, 30), (13, 50)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_an_unknown_weekday() {
+        assert!(parse_schedule(&schedule(&[("Funday", "08:30-09:50")])).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_ranges() {
+        for range in ["08:30", "08:30-", "8.30-9.50", "09:50-08:30", "08:30-08:30"] {
+            let raw = schedule(&[("Sunday", range)]);
+            assert!(parse_schedule(&raw).is_err(), "{range:?} parsed");
+        }
+    }
+
+    #[test]
+    fn overlapping_classes_conflict() {
+        let a = schedule(&[("Sunday", "08:30-09:50")]);
+        assert!(schedules_conflict(&a, &schedule(&[("Sun", "09:00-10:20")])));
+        assert!(!schedules_conflict(
+            &a,
+            &schedule(&[("Sun", "09:50-11:10")])
+        ));
+        assert!(!schedules_conflict(
+            &a,
+            &schedule(&[("Monday", "08:30-09:50")])
+        ));
+    }
+}
@@ -0,0 +1,99 @@
+use std::future::Future;
+
+use crate::ucam_cloud_api::{
+    ApiClient, CourseData, CourseSections, Result, SectionAction, SectionActionRequest, TokenState,
+};
+
+/// The requests selecting a section takes, implemented by [`ApiClient`] and by any mock scripting
+/// the server's answers instead.
+pub trait SectionApi: Clone + Send + Sync + 'static {
+    /// The access token the requests are sent with, `None` before logging in.
+    fn token(&self) -> Option<TokenState>;
+
+    fn fetch_course_sections(
+        &self,
+        course_code: &str,
+        student_id: &str,
+    ) -> impl Future<Output = Result<CourseSections>> + Send;
+
+    /// The course as the student sees it, telling whether selecting its sections is allowed.
+    fn fetch_course_data_as_student(
+        &self,
+        course_id: &str,
+    ) -> impl Future<Output = Result<CourseData>> + Send;
+
+    fn post_course_action(
+        &self,
+        course_id: &str,
+        action: &SectionActionRequest,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn select_section(
+        &self,
+        course_id: &str,
+        section_id: u64,
+    ) -> impl Future<Output = Result<()>> + Send {
+        section_action(self, course_id, section_id, SectionAction::Select)
+    }
+
+    /// Un-enrolls from a section, goes through the same endpoint as selecting one.
+    fn drop_section(
+        &self,
+        course_id: &str,
+        section_id: u64,
+    ) -> impl Future<Output = Result<()>> + Send {
+        section_action(self, course_id, section_id, SectionAction::Drop)
+    }
+
+    /// Joins the waitlist of a full section, goes through the same endpoint as selecting one.
+    fn join_waitlist(
+        &self,
+        course_id: &str,
+        section_id: u64,
+    ) -> impl Future<Output = Result<()>> + Send {
+        section_action(self, course_id, section_id, SectionAction::Waitlist)
+    }
+}
+
+async fn section_action(
+    api: &impl SectionApi,
+    course_id: &str,
+    section_id: u64,
+    action: SectionAction,
+) -> Result<()> {
+    let action = SectionActionRequest {
+        section_id,
+        action,
+        parent_course_code: course_id.to_string(),
+    };
+    api.post_course_action(course_id, &action).await
+}
+
+impl SectionApi for ApiClient {
+    fn token(&self) -> Option<TokenState> {
+        ApiClient::token(self)
+    }
+
+    fn fetch_course_sections(
+        &self,
+        course_code: &str,
+        student_id: &str,
+    ) -> impl Future<Output = Result<CourseSections>> + Send {
+        ApiClient::fetch_course_sections(self, course_code, student_id)
+    }
+
+    fn fetch_course_data_as_student(
+        &self,
+        course_id: &str,
+    ) -> impl Future<Output = Result<CourseData>> + Send {
+        ApiClient::fetch_course_data_as_student(self, course_id)
+    }
+
+    fn post_course_action(
+        &self,
+        course_id: &str,
+        action: &SectionActionRequest,
+    ) -> impl Future<Output = Result<()>> + Send {
+        ApiClient::post_course_action(self, course_id, action)
+    }
+}
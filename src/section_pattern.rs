@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use regex::Regex;
+
+use crate::config::{Config, MatchMode};
+
+/// A preferred section name compiled for the [`MatchMode`] of its course.
+#[derive(Clone)]
+pub enum SectionPattern {
+    Contains(String),
+    Exact(String),
+    Regex(Regex),
+}
+
+impl SectionPattern {
+    pub fn new(mode: MatchMode, preferred: &str) -> Result<Self> {
+        Ok(match mode {
+            MatchMode::Contains => SectionPattern::Contains(preferred.to_string()),
+            MatchMode::Exact => SectionPattern::Exact(preferred.trim().to_string()),
+            MatchMode::Regex => SectionPattern::Regex(
+                Regex::new(preferred)
+                    .with_context(|| format!("Invalid section name regex \"{preferred}\""))?,
+            ),
+        })
+    }
+
+    /// Whether `section_name` matches, `Contains` being a case-insensitive substring match so
+    /// `"B"` matches `"B"`, `"B1"` and `"AB"` alike.
+    pub fn matches(&self, section_name: &str) -> bool {
+        match self {
+            SectionPattern::Contains(preferred) => section_name
+                .to_ascii_lowercase()
+                .contains(&preferred.to_ascii_lowercase()),
+            SectionPattern::Exact(preferred) => section_name.trim().eq_ignore_ascii_case(preferred),
+            SectionPattern::Regex(regex) => regex.is_match(section_name),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            SectionPattern::Contains(preferred) | SectionPattern::Exact(preferred) => preferred,
+            SectionPattern::Regex(regex) => regex.as_str(),
+        }
+    }
+}
+
+impl std::fmt::Debug for SectionPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// Compiles the preferred sections of every course once, so a broken regex is reported before
+/// any course is polled.
+pub fn compile_preferred_sections(config: &Config) -> Result<HashMap<String, Vec<SectionPattern>>> {
+    config
+        .preferred_sections
+        .iter()
+        .map(|(course_code, sections)| {
+            let mode = config
+                .match_mode
+                .get(course_code)
+                .copied()
+                .unwrap_or_default();
+            let patterns = sections
+                .iter()
+                .map(|preferred| SectionPattern::new(mode, preferred))
+                .collect::<Result<Vec<_>>>()
+                .with_context(|| format!("Invalid preferred sections of course {course_code}"))?;
+            Ok((course_code.clone(), patterns))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matching(mode: MatchMode, preferred: &str) -> Vec<&'static str> {
+        let pattern = SectionPattern::new(mode, preferred).unwrap();
+        ["B", "b", "B1", "AB", "C"]
+            .into_iter()
+            .filter(|name| pattern.matches(name))
+            .collect()
+    }
+
+    #[test]
+    fn contains_matches_every_section_name_containing_it() {
+        assert_eq!(matching(MatchMode::Contains, "B"), ["B", "b", "B1", "AB"]);
+        assert_eq!(matching(MatchMode::Contains, "b1"), ["B1"]);
+    }
+
+    #[test]
+    fn exact_matches_the_whole_section_name_only() {
+        assert_eq!(matching(MatchMode::Exact, "B"), ["B", "b"]);
+        assert_eq!(matching(MatchMode::Exact, " B "), ["B", "b"]);
+    }
+
+    #[test]
+    fn regex_matches_as_written() {
+        assert_eq!(matching(MatchMode::Regex, "^B"), ["B", "B1"]);
+        assert_eq!(matching(MatchMode::Regex, "B$"), ["B", "AB"]);
+        assert_eq!(matching(MatchMode::Regex, "^B[0-9]*$"), ["B", "B1"]);
+    }
+
+    #[test]
+    fn invalid_regex_is_an_error() {
+        assert!(SectionPattern::new(MatchMode::Regex, "B(").is_err());
+    }
+}
@@ -0,0 +1,1439 @@
+//! The selection engine: polls a course's sections and selects the preferred one with a free
+//! seat, see [`auto_select_section`].
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{IsTerminal, Write},
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::color::Tone;
+use crate::config::{Config, FacultyPreference};
+use crate::events::{Event, EventSink, SectionSeats};
+use crate::history::History;
+use crate::local_time::to_local;
+use crate::metrics;
+use crate::notify::Notifier;
+use crate::schedule::schedules_conflict;
+use crate::section_api::SectionApi;
+use crate::section_pattern::SectionPattern;
+use crate::strategy::SelectionStrategy;
+use crate::ucam_cloud_api::{
+    ApiClient, ApiError, CacheInfo, CourseSection, CourseSections, SectionAction,
+    SectionActionRequest,
+};
+use crate::watch;
+
+/// The shortest poll interval allowed.
+pub const MIN_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How many failed waitlist joins of a section are retried, one per poll, before giving up on it.
+const MAX_WAITLIST_ATTEMPTS: u32 = 3;
+
+/// How [`auto_select_section`] polls and selects, built from the config with
+/// [`SelectionOptions::from_config`].
+#[derive(Debug, Clone)]
+pub struct SelectionOptions {
+    pub empty_poll_interval: Duration,
+    pub waiting_poll_interval: Duration,
+    pub max_waiting_poll_interval: Duration,
+    pub max_select_attempts: u32,
+    /// Cap of the poll cycles of a course, counting both the ones without a free seat and the
+    /// ones posting a select, unlimited without one.
+    pub max_attempts: Option<u32>,
+    pub max_concurrent_courses: usize,
+    /// How long none of the preferred sections have to have a free seat before joining the
+    /// waitlist of the most preferred one, never joins one without it.
+    pub waitlist_grace_period: Option<Duration>,
+    /// Orders the preferred sections with a free seat, the faculty and department preferences
+    /// still come before it.
+    pub strategy: Arc<dyn SelectionStrategy>,
+    /// Sections with free quota seats for this department are tried first.
+    pub department: Option<String>,
+    /// Only log the section actions that would be posted, never post them.
+    pub dry_run: bool,
+    /// Post a select for every preferred section with a free seat at once instead of only the
+    /// most preferred one.
+    pub race_sections: bool,
+    /// Give up on a course instead of only warning when a preferred section matches none of its
+    /// sections.
+    pub strict: bool,
+    /// Ask the server whether selecting is allowed before posting a select.
+    pub check_selection_allowed: bool,
+    /// Hide the countdown to the selection window opening.
+    pub quiet: bool,
+    /// Give up on every course not enrolled in by then.
+    pub deadline: Option<DateTime<Utc>>,
+    /// Wait a random delay up to this long before a course's first select, so every instance
+    /// of this tool doesn't post the instant the window opens.
+    pub humanize: Option<Duration>,
+    pub notifier: Notifier,
+    pub events: EventSink,
+    /// Records every poll and select, nothing is recorded without it.
+    pub history: Option<Arc<History>>,
+}
+
+impl SelectionOptions {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let options = Self {
+            empty_poll_interval: Duration::from_millis(config.empty_poll_interval_ms),
+            waiting_poll_interval: Duration::from_millis(config.waiting_poll_interval_ms),
+            max_waiting_poll_interval: Duration::from_millis(config.max_waiting_poll_interval_ms),
+            max_select_attempts: config.max_select_attempts,
+            max_attempts: config.max_attempts,
+            max_concurrent_courses: config.max_concurrent_courses,
+            waitlist_grace_period: config
+                .allow_waitlist
+                .then(|| Duration::from_secs(config.waitlist_grace_period_secs)),
+            strategy: config.prefer.strategy(),
+            department: config.department.clone(),
+            dry_run: false,
+            race_sections: config.race_preferred_sections,
+            strict: false,
+            check_selection_allowed: config.check_selection_allowed,
+            quiet: false,
+            deadline: None,
+            humanize: None,
+            notifier: Notifier::from_config(config),
+            events: EventSink::default(),
+            history: None,
+        };
+        for (name, interval) in [
+            ("empty_poll_interval_ms", options.empty_poll_interval),
+            ("waiting_poll_interval_ms", options.waiting_poll_interval),
+        ] {
+            if interval < MIN_POLL_INTERVAL {
+                anyhow::bail!(
+                    "`{name}` must be at least {}ms, got {}ms",
+                    MIN_POLL_INTERVAL.as_millis(),
+                    interval.as_millis()
+                );
+            }
+        }
+        if options.max_concurrent_courses == 0 {
+            anyhow::bail!("`max_concurrent_courses` must be at least 1");
+        }
+        if options.max_waiting_poll_interval < options.waiting_poll_interval {
+            anyhow::bail!(
+                "`max_waiting_poll_interval_ms` can't be less than `waiting_poll_interval_ms`"
+            );
+        }
+        Ok(options)
+    }
+
+    /// These options with the poll interval of a course, clamped to the minimum, as the waiting
+    /// poll interval. The backoff cap grows with it if needed.
+    pub fn with_poll_interval(mut self, course_code: &str, interval: Duration) -> Self {
+        if interval < MIN_POLL_INTERVAL {
+            warn!(
+                "The poll interval of {course_code} is below the minimum of {}ms, using that...",
+                MIN_POLL_INTERVAL.as_millis()
+            );
+        }
+        self.waiting_poll_interval = interval.max(MIN_POLL_INTERVAL);
+        self.max_waiting_poll_interval = self
+            .max_waiting_poll_interval
+            .max(self.waiting_poll_interval);
+        self
+    }
+
+    /// The random delay before a course's first select, zero unless humanizing.
+    fn humanize_delay(&self) -> Duration {
+        self.humanize_delay_with(&mut rand::rng())
+    }
+
+    fn humanize_delay_with(&self, rng: &mut impl Rng) -> Duration {
+        let max_ms = self.humanize.map_or(0, |max| max.as_millis() as u64);
+        Duration::from_millis(rng.random_range(0..=max_ms))
+    }
+
+    /// How long to wait before the next poll after `attempt` consecutive polls without a free
+    /// seat, the waiting poll interval doubles per attempt up to its cap and gets jittered so
+    /// every instance of this tool doesn't poll the server in lockstep.
+    pub fn next_backoff(&self, attempt: u32) -> Duration {
+        self.next_backoff_with(attempt, &mut rand::rng())
+    }
+
+    fn next_backoff_with(&self, attempt: u32, rng: &mut impl Rng) -> Duration {
+        let backoff = self
+            .waiting_poll_interval
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_waiting_poll_interval);
+        // Somewhere between half and all of the backoff, so it never drops below half of it.
+        let half_ms = (backoff.as_millis() / 2) as u64;
+        Duration::from_millis(half_ms + rng.random_range(0..=half_ms))
+    }
+}
+
+/// How the selection of a course ended.
+#[derive(Debug)]
+pub enum SelectionOutcome {
+    Enrolled {
+        section_name: String,
+        section_id: u64,
+    },
+    AlreadyEnrolled,
+    /// Joined the waitlist of a full section, `position` is the waitlist length before joining.
+    Waitlisted {
+        section_name: String,
+        position: usize,
+    },
+    WindowClosed,
+    NoSeatsYet,
+    /// A section could have been selected, but it's a dry run.
+    DryRun {
+        section_name: String,
+    },
+    Failed(String),
+    /// No preferred sections are configured for the course.
+    Skipped,
+    /// Stopped by Ctrl+C or SIGTERM before enrolling.
+    Cancelled,
+    /// The `--deadline` passed before enrolling.
+    DeadlineReached,
+}
+
+impl SelectionOutcome {
+    pub fn tone(&self) -> Tone {
+        match self {
+            SelectionOutcome::Enrolled { .. }
+            | SelectionOutcome::AlreadyEnrolled
+            | SelectionOutcome::Waitlisted { .. } => Tone::Success,
+            SelectionOutcome::NoSeatsYet
+            | SelectionOutcome::DryRun { .. }
+            | SelectionOutcome::Skipped
+            | SelectionOutcome::Cancelled => Tone::Pending,
+            SelectionOutcome::WindowClosed
+            | SelectionOutcome::Failed(_)
+            | SelectionOutcome::DeadlineReached => Tone::Error,
+        }
+    }
+}
+
+impl std::fmt::Display for SelectionOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SelectionOutcome::Enrolled {
+                section_name,
+                section_id,
+            } => write!(f, "Enrolled in section {section_name} (id {section_id})"),
+            SelectionOutcome::AlreadyEnrolled => write!(f, "Already enrolled"),
+            SelectionOutcome::Waitlisted {
+                section_name,
+                position,
+            } => write!(
+                f,
+                "Waitlisted for section {section_name}(about {position} ahead)"
+            ),
+            SelectionOutcome::WindowClosed => write!(f, "Selection window closed"),
+            SelectionOutcome::NoSeatsYet => write!(f, "No seats yet"),
+            SelectionOutcome::DryRun { section_name } => {
+                write!(f, "Would have selected section {section_name}(dry run)")
+            }
+            SelectionOutcome::Failed(reason) => write!(f, "Failed: {reason}"),
+            SelectionOutcome::Skipped => write!(f, "Skipped, no preferred sections"),
+            SelectionOutcome::Cancelled => write!(f, "Cancelled"),
+            SelectionOutcome::DeadlineReached => write!(f, "Deadline reached"),
+        }
+    }
+}
+
+/// Schedules of the sections held so far, by course code.
+pub type HeldSchedules = Arc<Mutex<HashMap<String, HashMap<String, String>>>>;
+
+/// Compiled preferred sections, by course code. Replaced as a whole when the config is reloaded.
+pub type PreferredSections = Arc<RwLock<HashMap<String, Vec<SectionPattern>>>>;
+
+/// State shared by every course task.
+#[derive(Clone)]
+pub struct SharedState {
+    pub held_schedules: HeldSchedules,
+    pub preferred_sections: PreferredSections,
+    pub cancel: CancellationToken,
+    /// Limits how many courses poll the server at once, a permit is only held for one poll cycle
+    /// so the courses waiting for a seat don't starve the others.
+    pub poll_permits: Arc<tokio::sync::Semaphore>,
+}
+
+/// Everything tied to the current login.
+#[derive(Clone)]
+pub struct Session<A = ApiClient> {
+    pub client: A,
+    pub user_id: String,
+}
+
+pub async fn auto_select_section<A: SectionApi>(
+    session: Session<A>,
+    course_code: String,
+    course_id: Option<String>,
+    faculty: FacultyPreference,
+    options: SelectionOptions,
+    shared: SharedState,
+    select_attempts: &mut u32,
+) -> Result<SelectionOutcome> {
+    let Session { client, user_id } = session;
+    let SharedState {
+        held_schedules,
+        preferred_sections: shared_preferred_sections,
+        cancel,
+        poll_permits,
+    } = shared;
+    let mut preferred_sections =
+        current_preferred_sections(&shared_preferred_sections, &course_code);
+    info!(
+        "Started auto section selection for course {}, preferred sections: {:?}",
+        course_code, preferred_sections
+    );
+    *select_attempts = 0;
+    let mut waiting_polls = 0;
+    let mut waited_for_opening = false;
+    let mut cycles = 0u32;
+    let mut validated = false;
+    // Selects answered with a `5xx`, backing off further every time.
+    let mut server_errors = 0;
+    let mut humanized = false;
+    let mut previous: Option<CourseSections> = None;
+    // The server's last explanation of why selecting isn't allowed, only logged when it changes.
+    let mut disallowed_message: Option<String> = None;
+    // Since when none of the preferred sections had a free seat.
+    let mut full_since: Option<std::time::Instant> = None;
+    // Failed waitlist joins by section id, a section is given up on after
+    // `MAX_WAITLIST_ATTEMPTS`.
+    let mut waitlist_failures: HashMap<u64, u32> = HashMap::new();
+    // Sections the server said clash with the schedule, only logged the first time.
+    let mut conflicting: HashSet<u64> = HashSet::new();
+    // The winner and the section ids of a race whose extra enrollments aren't dropped yet, kept
+    // until a poll shows them when confirming the enrollment fails.
+    let mut raced: Option<(u64, Vec<u64>)> = None;
+    loop {
+        // Reaching the deadline cancels too, so it's checked first to tell them apart.
+        if deadline_passed(&options) {
+            info!("{course_code} - Reached the deadline without enrolling, stopping...");
+            return Ok(SelectionOutcome::DeadlineReached);
+        }
+        // Only checked between requests, so a select that was already posted always completes.
+        if cancel.is_cancelled() {
+            return Ok(SelectionOutcome::Cancelled);
+        }
+        let reloaded = current_preferred_sections(&shared_preferred_sections, &course_code);
+        if !same_patterns(&reloaded, &preferred_sections) {
+            info!(
+                "{course_code} - Preferred sections changed from {:?} to {:?}",
+                preferred_sections, reloaded
+            );
+            preferred_sections = reloaded;
+            validated = false;
+        }
+        if preferred_sections.is_empty() {
+            info!("{course_code} - No preferred sections configured anymore, skipping...");
+            return Ok(SelectionOutcome::Skipped);
+        }
+        if client.token().is_none_or(|token| token.expires_soon()) {
+            return Err(ApiError::InvalidToken.into());
+        }
+        if let Some(max_attempts) = options.max_attempts.filter(|&max| cycles >= max) {
+            let reason = format!("Hit the cap of {max_attempts} attempts without enrolling");
+            error!("{course_code} - {reason}");
+            return Ok(SelectionOutcome::Failed(reason));
+        }
+        let permit = poll_permits.acquire().await?;
+        cycles += 1;
+        tracing::debug!("{course_code} - Attempt {cycles}");
+        let fetch = client.fetch_course_sections(&course_code, &user_id);
+        let course_info = match metrics::track_fetch(fetch).await {
+            Ok(course_info) => course_info,
+            Err(e) if e.is_timeout() || e.is_server_error() => {
+                warn!("{course_code} - Fetching the sections failed: {e}, retrying...");
+                drop(permit);
+                sleep_unless_cancelled(options.next_backoff(waiting_polls), &cancel).await;
+                waiting_polls = waiting_polls.saturating_add(1);
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(history) = &options.history {
+            history.record_poll(&course_info);
+        }
+        if options.events.is_ndjson() {
+            let sections = course_info
+                .sections
+                .iter()
+                .map(|s| SectionSeats {
+                    section_name: &s.section_name,
+                    seats_taken: s.seats_taken,
+                    total_seats: s.total_seats,
+                    waitlist_count: s.waitlist_count,
+                })
+                .collect();
+            let selection_open = course_info.selection_open;
+            let poll = Event::Poll {
+                selection_open,
+                sections,
+            };
+            options.events.emit(&course_code, poll);
+        }
+        let changes = previous
+            .replace(course_info.clone())
+            .map(|previous| watch::diff_sections(&previous, &course_info))
+            .unwrap_or_default();
+        for change in &changes {
+            info!("{} - {change}", course_info.course_name);
+            let event = Event::SeatChange {
+                section_name: &change.section_name,
+                seats_taken_before: change.seats_taken_before,
+                seats_taken: change.seats_taken,
+                total_seats: change.total_seats,
+            };
+            options.events.emit(&course_code, event);
+            // Told whether or not the select below wins it, so the student can still try by hand.
+            let opened = change.seats_taken_before >= change.total_seats
+                && change.seats_taken < change.total_seats;
+            if opened
+                && preferred_sections
+                    .iter()
+                    .any(|ps| ps.matches(&change.section_name))
+            {
+                let message = format!(
+                    "A seat opened in section {} of {}",
+                    change.section_name, course_info.course_name
+                );
+                // Sent in the background so it doesn't hold up the select.
+                options.notifier.notify_in_background(message);
+            }
+        }
+        // The sections may not be published yet on the first polls.
+        if !validated && !course_info.sections.is_empty() {
+            validated = true;
+            let unmatched: Vec<_> = preferred_sections
+                .iter()
+                .filter(|ps| {
+                    !course_info
+                        .sections
+                        .iter()
+                        .any(|s| ps.matches(&s.section_name))
+                })
+                .collect();
+            if !unmatched.is_empty() {
+                let names: Vec<_> = course_info
+                    .sections
+                    .iter()
+                    .map(|s| s.section_name.as_str())
+                    .collect();
+                let reason = format!(
+                    "The preferred sections {unmatched:?} match none of the sections {names:?}"
+                );
+                if options.strict {
+                    error!("{} - {reason}", course_info.course_name);
+                    return Ok(SelectionOutcome::Failed(reason));
+                }
+                warn!(
+                    "{} - {reason}, check the config for typos",
+                    course_info.course_name
+                );
+            }
+        }
+        if course_info.sections.iter().any(|s| {
+            s.is_enrolled
+                && preferred_sections
+                    .iter()
+                    .any(|ps| ps.matches(&s.section_name))
+        }) {
+            if let Some((winner_id, raced_ids)) = raced.take() {
+                // Kept is the winner, or the most preferred raced section if it didn't stick.
+                let enrolled = |id: &u64| {
+                    course_info
+                        .sections
+                        .iter()
+                        .any(|s| s.section_id == *id && s.is_enrolled)
+                };
+                let keep = Some(winner_id)
+                    .filter(enrolled)
+                    .or_else(|| raced_ids.iter().copied().find(enrolled));
+                if let Some(keep) = keep {
+                    drop_race_extras(&client, &course_code, &course_info, keep, &raced_ids).await;
+                }
+            }
+            info!(
+                "Already enrolled in course {}, skipping...",
+                course_info.course_name
+            );
+            return Ok(SelectionOutcome::AlreadyEnrolled);
+        }
+        if Utc::now() >= course_info.section_selection_end_time {
+            // Still waiting on a seat means it was open but never had one free for us.
+            let reason = if waiting_polls > 0 {
+                ", no preferred section had a free seat"
+            } else {
+                ""
+            };
+            info!(
+                "{} - The selection window closed at {} without enrolling in it{reason}, stopping...",
+                course_info.course_name,
+                to_local(&course_info.section_selection_end_time)
+            );
+            return Ok(SelectionOutcome::WindowClosed);
+        }
+        if !course_info.selection_open {
+            let now = Utc::now();
+            let wake_at = course_info.section_selection_start_time;
+            if wake_at > now {
+                if !waited_for_opening {
+                    info!(
+                        "{} - Selection opens at {}, waiting...",
+                        course_info.course_name,
+                        to_local(&course_info.section_selection_start_time)
+                    );
+                }
+                drop(permit);
+                // Polled again every waiting interval in case the start time gets moved.
+                count_down_to_opening(
+                    &course_info.course_name,
+                    wake_at,
+                    options.waiting_poll_interval,
+                    options.quiet,
+                    &cancel,
+                )
+                .await;
+            } else {
+                drop(permit);
+                sleep_unless_cancelled(options.empty_poll_interval, &cancel).await;
+            }
+            waited_for_opening = true;
+            continue;
+        }
+        if waited_for_opening {
+            waited_for_opening = false;
+            // In the background as well, the first select is the one that counts the most.
+            options.notifier.notify_in_background(format!(
+                "Section selection opened for {}",
+                course_info.course_name
+            ));
+        }
+        if course_info.sections.is_empty() {
+            drop(permit);
+            sleep_unless_cancelled(options.empty_poll_interval, &cancel).await;
+            continue;
+        }
+        let clashes = |section: &CourseSection| {
+            let held_schedules = held_schedules.lock().unwrap();
+            held_schedules.iter().any(|(held_course_code, schedule)| {
+                *held_course_code != course_code && schedules_conflict(&section.schedule, schedule)
+            })
+        };
+        for s in &course_info.sections {
+            let preferred = preferred_sections
+                .iter()
+                .any(|ps| ps.matches(&s.section_name));
+            if s.have_conflict && preferred && conflicting.insert(s.section_id) {
+                info!(
+                    "{} - Skipping section {}, the server says it clashes with {}",
+                    course_info.course_name, s.section_name, s.conflict_with
+                );
+            }
+        }
+        let selectable: Vec<&CourseSection> = course_info
+            .sections
+            .iter()
+            .filter(|s| {
+                s.is_selectable()
+                    && !s.have_conflict
+                    && s.available_seats() > 0
+                    && !faculty.avoids(&s.faculty_name, &s.faculty_code)
+                    && !clashes(s)
+            })
+            .collect();
+        // The department and faculty preferences come first, the strategy only chooses between
+        // the sections they rank the same.
+        let mut tiers: BTreeMap<(bool, usize), Vec<&CourseSection>> = BTreeMap::new();
+        for s in selectable {
+            let no_department_seats = options
+                .department
+                .as_ref()
+                .is_some_and(|department| s.available_for_department(department) == 0);
+            let faculty_rank = faculty
+                .rank(&s.faculty_name, &s.faculty_code)
+                .unwrap_or(usize::MAX);
+            tiers
+                .entry((no_department_seats, faculty_rank))
+                .or_default()
+                .push(s);
+        }
+        let chosen = tiers
+            .values()
+            .find_map(|tier| options.strategy.choose(tier, &preferred_sections));
+        let Some(section) = chosen else {
+            let full_for = full_since
+                .get_or_insert_with(std::time::Instant::now)
+                .elapsed();
+            let waitlist_section = preferred_sections.iter().find_map(|preferred| {
+                course_info.sections.iter().find(|s| {
+                    preferred.matches(&s.section_name)
+                        && s.is_active
+                        && !s.have_conflict
+                        && !faculty.avoids(&s.faculty_name, &s.faculty_code)
+                        && !clashes(s)
+                        && waitlist_failures.get(&s.section_id).copied().unwrap_or(0)
+                            < MAX_WAITLIST_ATTEMPTS
+                })
+            });
+            let waitlist_due = options
+                .waitlist_grace_period
+                .is_some_and(|grace_period| full_for >= grace_period);
+            if let Some(section) = waitlist_section.filter(|_| waitlist_due) {
+                let outcome = SelectionOutcome::Waitlisted {
+                    section_name: section.section_name.clone(),
+                    position: section.waitlist_count,
+                };
+                if options.dry_run {
+                    info!(
+                        "{} - Dry run, would have joined the waitlist of section {}",
+                        course_info.course_name, section.section_name
+                    );
+                    return Ok(SelectionOutcome::DryRun {
+                        section_name: section.section_name.clone(),
+                    });
+                }
+                let failures = waitlist_failures.entry(section.section_id).or_default();
+                let attempt = *failures + 1;
+                let event = Event::WaitlistAttempt {
+                    section_name: &section.section_name,
+                    attempt,
+                    max_attempts: MAX_WAITLIST_ATTEMPTS,
+                };
+                options.events.emit(&course_code, event);
+                let posted_at = std::time::Instant::now();
+                let join = client.join_waitlist(&course_code, section.section_id);
+                let joined = metrics::track_waitlist(join).await;
+                if let Some(history) = &options.history {
+                    let error = joined.as_ref().err().map(|e| e.to_string());
+                    let latency = posted_at.elapsed();
+                    history.record_waitlist_join(
+                        &course_code,
+                        &section.section_name,
+                        error.as_deref(),
+                        latency,
+                    );
+                }
+                match joined {
+                    Ok(()) => {
+                        info!("{} - {outcome}", course_info.course_name);
+                        return Ok(outcome);
+                    }
+                    Err(e) => {
+                        *failures = attempt;
+                        warn!(
+                            "{} - Attempt {attempt}/{MAX_WAITLIST_ATTEMPTS} to join the waitlist of section {} failed: {e}",
+                            course_info.course_name, section.section_name
+                        );
+                        if attempt >= MAX_WAITLIST_ATTEMPTS {
+                            warn!(
+                                "{} - Giving up on the waitlist of section {}",
+                                course_info.course_name, section.section_name
+                            );
+                        }
+                        let event = Event::WaitlistFailed {
+                            section_name: &section.section_name,
+                            attempt,
+                            error: e.to_string(),
+                        };
+                        options.events.emit(&course_code, event);
+                    }
+                }
+            }
+            drop(permit);
+            sleep_unless_cancelled(options.next_backoff(waiting_polls), &cancel).await;
+            waiting_polls = waiting_polls.saturating_add(1);
+            continue;
+        };
+        waiting_polls = 0;
+        full_since = None;
+        if let Some(course_id) = course_id
+            .as_deref()
+            .filter(|_| options.check_selection_allowed)
+        {
+            match client.fetch_course_data_as_student(course_id).await {
+                Ok(course_data) if !course_data.selection_allowed => {
+                    let message = course_data.selection_message;
+                    if disallowed_message.as_ref() != Some(&message) {
+                        warn!(
+                            "{} - The server doesn't allow selecting yet: {message}, waiting...",
+                            course_info.course_name
+                        );
+                        disallowed_message = Some(message);
+                    }
+                    let wait = server_cache_wait(
+                        &course_data.cache_info,
+                        course_info.section_selection_end_time,
+                    )
+                    .filter(|&wait| wait > options.waiting_poll_interval);
+                    if let Some(wait) = wait {
+                        info!(
+                            "{} - The server caches this answer, not asking again for {}s...",
+                            course_info.course_name,
+                            wait.as_secs()
+                        );
+                    }
+                    drop(permit);
+                    let wait = wait.unwrap_or(options.waiting_poll_interval);
+                    sleep_unless_cancelled(wait, &cancel).await;
+                    continue;
+                }
+                Ok(_) => disallowed_message = None,
+                // Only a hint, the select itself still tells whether it's allowed.
+                Err(e) => warn!(
+                    "{} - Checking whether selecting is allowed failed: {e}",
+                    course_info.course_name
+                ),
+            }
+        }
+        let section_id = section.section_id;
+        let action = SectionActionRequest {
+            parent_course_code: course_code.to_string(),
+            section_id,
+            action: SectionAction::Select,
+        };
+        if options.dry_run {
+            info!(
+                "{} - Dry run, would have posted action {:?} for section {} (id {}) of course {}",
+                course_info.course_name,
+                action.action,
+                section.section_name,
+                action.section_id,
+                action.parent_course_code
+            );
+            return Ok(SelectionOutcome::DryRun {
+                section_name: section.section_name.clone(),
+            });
+        }
+        if !humanized && options.humanize.is_some() {
+            humanized = true;
+            let delay = options.humanize_delay();
+            info!(
+                "{} - Waiting {}ms before the first select...",
+                course_info.course_name,
+                delay.as_millis()
+            );
+            sleep_unless_cancelled(delay, &cancel).await;
+            if deadline_passed(&options) {
+                info!("{course_code} - Reached the deadline without enrolling, stopping...");
+                return Ok(SelectionOutcome::DeadlineReached);
+            }
+            if cancel.is_cancelled() {
+                return Ok(SelectionOutcome::Cancelled);
+            }
+            // The seats may have changed while waiting, so poll again before selecting.
+            drop(permit);
+            continue;
+        }
+        *select_attempts += 1;
+        info!(
+            "{} - Attempt {}/{} to select section {} (id {section_id})",
+            course_info.course_name,
+            select_attempts,
+            options.max_select_attempts,
+            section.section_name
+        );
+        let attempt = Event::SelectAttempt {
+            section_name: &section.section_name,
+            attempt: *select_attempts,
+            max_attempts: options.max_select_attempts,
+        };
+        options.events.emit(&course_code, attempt);
+        let candidates: Vec<&CourseSection> = if options.race_sections {
+            tiers
+                .values()
+                .flat_map(|tier| options.strategy.rank(tier, &preferred_sections))
+                .collect()
+        } else {
+            vec![section]
+        };
+        let racing = candidates.len() > 1;
+        let posted_at = std::time::Instant::now();
+        let posted = if racing {
+            metrics::track_select(race_select(&client, &course_code, &candidates)).await
+        } else {
+            metrics::track_select(client.post_course_action(&course_code, &action))
+                .await
+                .map(|()| section_id)
+        };
+        if let Some(history) = &options.history {
+            let (section_name, error) = match &posted {
+                Ok(winner_id) => {
+                    let winner = candidates.iter().find(|s| s.section_id == *winner_id);
+                    let name = winner.map_or(&section.section_name, |s| &s.section_name);
+                    (name, None)
+                }
+                Err(e) => (&section.section_name, Some(e.to_string())),
+            };
+            let latency = posted_at.elapsed();
+            history.record_select(&course_code, section_name, error.as_deref(), latency);
+        }
+        let failure = match posted {
+            Ok(winner_id) => 'confirm: {
+                let section = candidates
+                    .iter()
+                    .find(|s| s.section_id == winner_id)
+                    .copied()
+                    .unwrap_or(section);
+                let section_id = section.section_id;
+                if racing {
+                    raced = Some((
+                        section_id,
+                        candidates.iter().map(|s| s.section_id).collect(),
+                    ));
+                }
+                // A timed out or failed confirmation is retried like a failed select, the next
+                // poll sees the enrollment if the select went through.
+                let fetch = client.fetch_course_sections(&course_code, &user_id);
+                let confirmation = match metrics::track_fetch(fetch).await {
+                    Ok(confirmation) => confirmation,
+                    Err(e) if e.is_timeout() || e.is_server_error() => {
+                        warn!("{course_code} - Confirming the enrollment failed: {e}");
+                        break 'confirm e;
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+                if let Some((winner_id, raced_ids)) = raced.take() {
+                    drop_race_extras(&client, &course_code, &confirmation, winner_id, &raced_ids)
+                        .await;
+                }
+                if confirmation
+                    .sections
+                    .iter()
+                    .any(|s| s.section_id == section_id && s.is_enrolled)
+                {
+                    let outcome = SelectionOutcome::Enrolled {
+                        section_name: section.section_name.clone(),
+                        section_id,
+                    };
+                    info!("{} - {outcome}", course_info.course_name);
+                    let enrolled = Event::Enrolled {
+                        section_name: &section.section_name,
+                    };
+                    options.events.emit(&course_code, enrolled);
+                    held_schedules
+                        .lock()
+                        .unwrap()
+                        .insert(course_code.clone(), section.schedule.clone());
+                    options
+                        .notifier
+                        .notify(&format!(
+                            "Enrolled in section {} of {}",
+                            section.section_name, course_info.course_name
+                        ))
+                        .await;
+                    return Ok(outcome);
+                }
+                warn!(
+                    "{} - Selecting section {} (id {section_id}) succeeded but the enrollment \
+                     didn't stick",
+                    course_info.course_name, section.section_name
+                );
+                ApiError::Failed {
+                    context: "Select section",
+                    message: format!(
+                        "Not enrolled in section {} (id {section_id}) after selecting it",
+                        section.section_name
+                    ),
+                }
+            }
+            Err(ApiError::WindowClosed(message)) => {
+                info!(
+                    "{} - The selection window closed: {message}, stopping...",
+                    course_info.course_name
+                );
+                return Ok(SelectionOutcome::WindowClosed);
+            }
+            Err(ApiError::AlreadyEnrolled(message)) => {
+                info!(
+                    "{} - Already enrolled: {message}, skipping...",
+                    course_info.course_name
+                );
+                return Ok(SelectionOutcome::AlreadyEnrolled);
+            }
+            Err(e) => e,
+        };
+        let failed = Event::SelectFailed {
+            section_name: &section.section_name,
+            attempt: *select_attempts,
+            error: failure.to_string(),
+        };
+        options.events.emit(&course_code, failed);
+        if *select_attempts >= options.max_select_attempts {
+            let reason = format!(
+                "Gave up after {} failed attempts to select a section, last error: {failure}",
+                select_attempts
+            );
+            error!("{} - {reason}", course_info.course_name);
+            return Ok(SelectionOutcome::Failed(reason));
+        }
+        warn!(
+            "{} - Attempt {}/{} to select section {} (id {section_id}) failed: {failure}, \
+             retrying...",
+            course_info.course_name,
+            select_attempts,
+            options.max_select_attempts,
+            section.section_name
+        );
+        // Every failed select backs off before the next poll, only the server errors make the
+        // wait grow.
+        drop(permit);
+        sleep_unless_cancelled(options.next_backoff(server_errors), &cancel).await;
+        if failure.is_server_error() {
+            server_errors = server_errors.saturating_add(1);
+        }
+    }
+}
+
+fn current_preferred_sections(
+    preferred_sections: &PreferredSections,
+    course_code: &str,
+) -> Vec<SectionPattern> {
+    preferred_sections
+        .read()
+        .unwrap()
+        .get(course_code)
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn same_patterns(a: &[SectionPattern], b: &[SectionPattern]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.as_str() == b.as_str())
+}
+
+/// Whether the `--deadline` of the run is reached, never without one.
+pub fn deadline_passed(options: &SelectionOptions) -> bool {
+    options
+        .deadline
+        .is_some_and(|deadline| Utc::now() >= deadline)
+}
+
+/// Asking again this close to the end of the window ignores the server's cache, a stale answer
+/// costs less than missing the window.
+const WINDOW_CLOSING_SOON: Duration = Duration::from_secs(120);
+
+/// How long to wait for the server's cached answer to expire, never past the point where the
+/// window closes soon. `None` when there's nothing to wait for.
+fn server_cache_wait(cache_info: &CacheInfo, window_end: DateTime<Utc>) -> Option<Duration> {
+    let now = Utc::now();
+    let expires_in = cache_info.expires_in(now)?;
+    let until_closing_soon = (window_end - now)
+        .to_std()
+        .ok()?
+        .checked_sub(WINDOW_CLOSING_SOON)?;
+    Some(expires_in.min(until_closing_soon)).filter(|wait| !wait.is_zero())
+}
+
+/// Sleeps for `duration`, waking up early once `cancel` is cancelled.
+pub async fn sleep_unless_cancelled(duration: Duration, cancel: &CancellationToken) {
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => {}
+        _ = cancel.cancelled() => {}
+    }
+}
+
+/// Sleeps until `opens_at` or for `poll_interval`, whichever comes first, showing the time left
+/// until `opens_at` every second when stderr is a terminal.
+async fn count_down_to_opening(
+    course_name: &str,
+    opens_at: DateTime<Utc>,
+    poll_interval: Duration,
+    quiet: bool,
+    cancel: &CancellationToken,
+) {
+    let show = !quiet && std::io::stderr().is_terminal();
+    let until_open = (opens_at - Utc::now()).to_std().unwrap_or_default();
+    let wake_at = tokio::time::Instant::now() + poll_interval.min(until_open);
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep_until(wake_at) => break,
+            _ = cancel.cancelled() => break,
+            _ = tick.tick(), if show => {
+                let left = (opens_at - Utc::now()).num_seconds().max(0);
+                eprint!(
+                    "\r{course_name} - Selection opens in {:02}:{:02}:{:02}(at {})",
+                    left / 3600,
+                    left / 60 % 60,
+                    left % 60,
+                    to_local(&opens_at).format("%H:%M:%S")
+                );
+                _ = std::io::stderr().flush();
+            }
+        }
+    }
+    if show {
+        // Clears the countdown line for the logs.
+        eprint!("\r\x1b[2K");
+    }
+}
+
+/// Posts a select for every candidate section at once, returning the first section the server
+/// accepted and aborting the other requests. An aborted request may still have been processed by
+/// the server, so any extra enrollment has to be dropped afterwards.
+async fn race_select<A: SectionApi>(
+    client: &A,
+    course_code: &str,
+    candidates: &[&CourseSection],
+) -> Result<u64, ApiError> {
+    let mut join_set = tokio::task::JoinSet::new();
+    for section in candidates {
+        let client = client.clone();
+        let course_code = course_code.to_string();
+        let section_id = section.section_id;
+        join_set.spawn(async move {
+            client
+                .select_section(&course_code, section_id)
+                .await
+                .map(|()| section_id)
+        });
+    }
+    let mut last_error = ApiError::MissingData;
+    while let Some(result) = join_set.join_next().await {
+        match result {
+            Ok(Ok(section_id)) => {
+                join_set.abort_all();
+                return Ok(section_id);
+            }
+            Ok(Err(e)) => last_error = e,
+            Err(e) => {
+                last_error = ApiError::Failed {
+                    context: "Race select",
+                    message: e.to_string(),
+                }
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Drops the sections of `raced` enrolled in besides `keep`, the aborted selects of a race may
+/// still have reached the server.
+async fn drop_race_extras<A: SectionApi>(
+    client: &A,
+    course_code: &str,
+    course: &CourseSections,
+    keep: u64,
+    raced: &[u64],
+) {
+    for extra in course
+        .sections
+        .iter()
+        .filter(|s| s.is_enrolled && s.section_id != keep && raced.contains(&s.section_id))
+    {
+        warn!(
+            "{} - Also enrolled in section {} by the race, dropping it...",
+            course.course_name, extra.section_name
+        );
+        if let Err(e) = client.drop_section(course_code, extra.section_id).await {
+            error!(
+                "{} - Dropping section {} failed: {e}",
+                course.course_name, extra.section_name
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use chrono::TimeDelta;
+    use rand::{SeedableRng, rngs::StdRng};
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::config::MatchMode;
+    use crate::ucam_cloud_api::{CourseData, TokenState};
+
+    fn options() -> SelectionOptions {
+        let config: Config = toml::from_str(
+            r#"
+            waiting_poll_interval_ms = 1000
+            max_waiting_poll_interval_ms = 8000
+
+            [preferred_sections]
+            "#,
+        )
+        .unwrap();
+        SelectionOptions::from_config(&config).unwrap()
+    }
+
+    /// The shortest and longest of many backoffs after `attempt` polls.
+    fn backoff_bounds(options: &SelectionOptions, attempt: u32, rng: &mut StdRng) -> (u64, u64) {
+        let backoffs: Vec<u64> = (0..1000)
+            .map(|_| options.next_backoff_with(attempt, rng).as_millis() as u64)
+            .collect();
+        (
+            *backoffs.iter().min().unwrap(),
+            *backoffs.iter().max().unwrap(),
+        )
+    }
+
+    #[test]
+    fn backoff_stays_between_half_the_interval_and_the_cap() {
+        let options = options();
+        let mut rng = StdRng::seed_from_u64(18);
+        for attempt in [0, 1, 2, 3, 4, 10, 40, u32::MAX] {
+            let (min, max) = backoff_bounds(&options, attempt, &mut rng);
+            assert!(min >= 500, "attempt {attempt} waited {min}ms");
+            assert!(max <= 8000, "attempt {attempt} waited {max}ms");
+        }
+    }
+
+    #[test]
+    fn backoff_grows_until_capped() {
+        let options = options();
+        let mut rng = StdRng::seed_from_u64(18);
+        // Three doublings of 1000ms reach the 8000ms cap.
+        for attempt in 0..3 {
+            let (_, max) = backoff_bounds(&options, attempt, &mut rng);
+            let (next_min, _) = backoff_bounds(&options, attempt + 1, &mut rng);
+            assert!(
+                next_min >= max,
+                "attempt {attempt} waited up to {max}ms, then {next_min}ms"
+            );
+        }
+        let (min, max) = backoff_bounds(&options, 3, &mut rng);
+        assert!(min >= 4000 && max <= 8000);
+    }
+
+    #[test]
+    fn humanize_delay_stays_within_the_configured_range() {
+        let mut options = options();
+        let mut rng = StdRng::seed_from_u64(97);
+        assert!(options.humanize_delay_with(&mut rng).is_zero());
+
+        options.humanize = Some(Duration::from_millis(300));
+        let delays: Vec<Duration> = (0..1000)
+            .map(|_| options.humanize_delay_with(&mut rng))
+            .collect();
+        assert!(
+            delays
+                .iter()
+                .all(|&delay| delay <= Duration::from_millis(300))
+        );
+        // Spread over the range rather than stuck at one end of it.
+        assert!(delays.iter().any(|delay| delay.as_millis() < 100));
+        assert!(delays.iter().any(|delay| delay.as_millis() > 200));
+    }
+
+    const COURSE_CODE: &str = "CSE 1111";
+
+    #[derive(Default)]
+    struct MockState {
+        /// Answers of the section fetches in order, the last one is repeated once they run out.
+        polls: VecDeque<Value>,
+        /// Errors of the section actions in order, the actions succeed once they run out.
+        action_errors: VecDeque<ApiError>,
+        /// Errors answering the fetches, by the number of the fetch counting from 1.
+        fetch_errors: HashMap<usize, ApiError>,
+        /// The sections selected and not dropped since, shown as enrolled by the fetches after it.
+        enrolled: HashSet<u64>,
+        fetches: usize,
+        actions: Vec<(u64, SectionAction)>,
+    }
+
+    /// A [`SectionApi`] answering from a script instead of a server.
+    #[derive(Clone)]
+    struct MockApi {
+        state: Arc<Mutex<MockState>>,
+    }
+
+    impl MockApi {
+        fn new(polls: Vec<Value>, action_errors: Vec<ApiError>) -> Self {
+            let state = MockState {
+                polls: polls.into(),
+                action_errors: action_errors.into(),
+                ..Default::default()
+            };
+            Self {
+                state: Arc::new(Mutex::new(state)),
+            }
+        }
+
+        /// Answers fetch number `fetch` with `e` instead of a poll.
+        fn failing_fetch(self, fetch: usize, e: ApiError) -> Self {
+            self.state.lock().unwrap().fetch_errors.insert(fetch, e);
+            self
+        }
+
+        fn fetches(&self) -> usize {
+            self.state.lock().unwrap().fetches
+        }
+
+        fn enrolled(&self) -> HashSet<u64> {
+            self.state.lock().unwrap().enrolled.clone()
+        }
+
+        fn actions(&self) -> Vec<(u64, SectionAction)> {
+            self.state.lock().unwrap().actions.clone()
+        }
+    }
+
+    impl SectionApi for MockApi {
+        fn token(&self) -> Option<TokenState> {
+            Some(TokenState {
+                access_token: "access".to_string(),
+                expires_at: Utc::now() + TimeDelta::hours(1),
+            })
+        }
+
+        async fn fetch_course_sections(
+            &self,
+            _course_code: &str,
+            _student_id: &str,
+        ) -> Result<CourseSections, ApiError> {
+            let mut state = self.state.lock().unwrap();
+            state.fetches += 1;
+            let fetch = state.fetches;
+            if let Some(e) = state.fetch_errors.remove(&fetch) {
+                return Err(e);
+            }
+            let poll = if state.polls.len() > 1 {
+                state.polls.pop_front().unwrap()
+            } else {
+                state.polls[0].clone()
+            };
+            let mut course: CourseSections = serde_json::from_value(poll)?;
+            for section in &mut course.sections {
+                section.is_enrolled |= state.enrolled.contains(&section.section_id);
+            }
+            Ok(course)
+        }
+
+        async fn fetch_course_data_as_student(
+            &self,
+            _course_id: &str,
+        ) -> Result<CourseData, ApiError> {
+            Err(ApiError::MissingData)
+        }
+
+        async fn post_course_action(
+            &self,
+            _course_id: &str,
+            action: &SectionActionRequest,
+        ) -> Result<(), ApiError> {
+            let mut state = self.state.lock().unwrap();
+            state.actions.push((action.section_id, action.action));
+            if let Some(e) = state.action_errors.pop_front() {
+                return Err(e);
+            }
+            match action.action {
+                SectionAction::Select => {
+                    state.enrolled.insert(action.section_id);
+                }
+                SectionAction::Drop => {
+                    state.enrolled.remove(&action.section_id);
+                }
+                SectionAction::Waitlist => {}
+            }
+            Ok(())
+        }
+    }
+
+    /// An open course whose section B has `b_seats_taken` of its 40 seats taken, section A is
+    /// full. Its selection window ends `ends_in` from now.
+    fn course(b_seats_taken: usize, ends_in: TimeDelta) -> Value {
+        let section = |section_id: u64, section_name: &str, seats_taken: usize| {
+            json!({
+                "section_id": section_id,
+                "section_name": section_name,
+                "total_seats": 40,
+                "seats_taken": seats_taken,
+                "is_enrolled": false,
+                "faculty_name": "Faculty",
+                "faculty_email": "faculty@uiu.ac.bd"
+            })
+        };
+        let now = Utc::now();
+        json!({
+            "course_code": COURSE_CODE,
+            "course_name": "Structured Programming Language",
+            "sections": [section(1, "A", 40), section(2, "B", b_seats_taken)],
+            "selection_open": true,
+            "running_session": "Fall 2026",
+            "credits": 3,
+            "section_selection_start_time": now - TimeDelta::hours(1),
+            "section_selection_end_time": now + ends_in
+        })
+    }
+
+    /// Options polling every millisecond, giving up after two failed selects.
+    fn fast_options() -> SelectionOptions {
+        let config: Config = toml::from_str(
+            r#"
+            max_select_attempts = 2
+            max_attempts = 20
+
+            [preferred_sections]
+            "#,
+        )
+        .unwrap();
+        let mut options = SelectionOptions::from_config(&config).unwrap();
+        options.empty_poll_interval = Duration::from_millis(1);
+        options.waiting_poll_interval = Duration::from_millis(1);
+        options.max_waiting_poll_interval = Duration::from_millis(1);
+        options
+    }
+
+    /// Runs the selection of [`COURSE_CODE`] preferring section B.
+    async fn select(api: &MockApi) -> Result<SelectionOutcome> {
+        select_with(api, fast_options(), &["B"]).await
+    }
+
+    /// Runs the selection of [`COURSE_CODE`] with `options`, preferring the `preferred` sections
+    /// in order.
+    async fn select_with(
+        api: &MockApi,
+        options: SelectionOptions,
+        preferred: &[&str],
+    ) -> Result<SelectionOutcome> {
+        let preferred = preferred
+            .iter()
+            .map(|name| SectionPattern::new(MatchMode::Exact, name))
+            .collect::<Result<Vec<_>>>()?;
+        let shared = SharedState {
+            held_schedules: Arc::default(),
+            preferred_sections: Arc::new(
+                HashMap::from([(COURSE_CODE.to_string(), preferred)]).into(),
+            ),
+            cancel: CancellationToken::new(),
+            poll_permits: Arc::new(tokio::sync::Semaphore::new(1)),
+        };
+        let session = Session {
+            client: api.clone(),
+            user_id: "0112330000".to_string(),
+        };
+        let mut select_attempts = 0;
+        auto_select_section(
+            session,
+            COURSE_CODE.to_string(),
+            None,
+            FacultyPreference::default(),
+            options,
+            shared,
+            &mut select_attempts,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn selects_the_seat_opening_on_the_third_poll() {
+        let open = TimeDelta::hours(1);
+        let api = MockApi::new(
+            vec![course(40, open), course(40, open), course(39, open)],
+            Vec::new(),
+        );
+
+        let outcome = select(&api).await.unwrap();
+        let SelectionOutcome::Enrolled { section_name, .. } = &outcome else {
+            panic!("{outcome:?}");
+        };
+        assert_eq!(section_name, "B");
+        assert!(matches!(api.actions()[..], [(2, SectionAction::Select)]));
+        // The three polls and the confirmation of the select.
+        assert_eq!(api.fetches(), 4);
+    }
+
+    #[tokio::test]
+    async fn stops_once_the_window_closed() {
+        let api = MockApi::new(vec![course(39, -TimeDelta::minutes(1))], Vec::new());
+
+        let outcome = select(&api).await.unwrap();
+        assert!(
+            matches!(outcome, SelectionOutcome::WindowClosed),
+            "{outcome:?}"
+        );
+        assert!(api.actions().is_empty());
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_failed_selects() {
+        let seat_taken = || ApiError::SeatTaken("The section is full".to_string());
+        let api = MockApi::new(
+            vec![course(39, TimeDelta::hours(1))],
+            vec![seat_taken(), seat_taken()],
+        );
+
+        let outcome = select(&api).await.unwrap();
+        assert!(
+            matches!(outcome, SelectionOutcome::Failed(_)),
+            "{outcome:?}"
+        );
+        assert_eq!(api.actions().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn already_enrolled_select_is_enrolled() {
+        let already_enrolled = ApiError::AlreadyEnrolled("You are already enrolled".to_string());
+        let api = MockApi::new(
+            vec![course(39, TimeDelta::hours(1))],
+            vec![already_enrolled],
+        );
+
+        let outcome = select(&api).await.unwrap();
+        assert!(
+            matches!(outcome, SelectionOutcome::AlreadyEnrolled),
+            "{outcome:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_race_confirmation_drops_the_extras_on_the_next_poll() {
+        let mut open = course(39, TimeDelta::hours(1));
+        open["sections"][0]["seats_taken"] = json!(39);
+        let server_error = ApiError::ServerError {
+            status: reqwest::StatusCode::BAD_GATEWAY,
+        };
+        // The confirmation of the race is the second fetch.
+        let api = MockApi::new(vec![open], Vec::new()).failing_fetch(2, server_error);
+        let mut options = fast_options();
+        options.race_sections = true;
+
+        let outcome = select_with(&api, options, &["B", "A"]).await.unwrap();
+        assert!(
+            matches!(outcome, SelectionOutcome::AlreadyEnrolled),
+            "{outcome:?}"
+        );
+        let actions = api.actions();
+        let selects = actions
+            .iter()
+            .filter(|(_, action)| matches!(action, SectionAction::Select))
+            .count();
+        let drops = actions
+            .iter()
+            .filter(|(_, action)| matches!(action, SectionAction::Drop))
+            .count();
+        assert_eq!((selects, drops), (2, 1), "{actions:?}");
+        assert_eq!(api.enrolled().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn backs_off_between_failed_selects() {
+        let seat_taken = || ApiError::SeatTaken("The section is full".to_string());
+        let api = MockApi::new(
+            vec![course(39, TimeDelta::hours(1))],
+            vec![seat_taken(), seat_taken()],
+        );
+        let mut options = fast_options();
+        options.waiting_poll_interval = Duration::from_millis(200);
+        options.max_waiting_poll_interval = Duration::from_millis(200);
+
+        let started = std::time::Instant::now();
+        let outcome = select_with(&api, options, &["B"]).await.unwrap();
+        assert!(
+            matches!(outcome, SelectionOutcome::Failed(_)),
+            "{outcome:?}"
+        );
+        // At least half of the waiting poll interval between the two selects.
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+}
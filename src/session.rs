@@ -0,0 +1,69 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+
+use crate::ucam_cloud_api::Login;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SavedSession {
+    user_id: String,
+    login: Login,
+}
+
+/// `$XDG_CACHE_HOME/uiu-section/session.json`, `~/.cache/uiu-section/session.json` when unset.
+pub fn session_path() -> Option<PathBuf> {
+    let cache_dir = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache_dir.join("uiu-section").join("session.json"))
+}
+
+/// The saved login of `user_id`, if its refresh token hasn't expired yet.
+pub fn load_session(path: &Path, user_id: &str) -> Option<Login> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let saved: SavedSession = serde_json::from_str(&content).ok()?;
+    (saved.user_id == user_id && saved.login.refresh_token_expires_at > Utc::now())
+        .then_some(saved.login)
+}
+
+/// Saves the login readable by the current user only, as it's as good as the password until it
+/// expires.
+pub fn save_session(path: &Path, user_id: &str, login: &Login) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create \"{}\"", dir.display()))?;
+    }
+    let content = serde_json::to_string(&SavedSession {
+        user_id: user_id.to_string(),
+        login: login.clone(),
+    })?;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // `mode` only applies to newly created files.
+        if path.exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to open \"{}\"", path.display()))?;
+    std::io::Write::write_all(&mut file, content.as_bytes())
+        .with_context(|| format!("Failed to write \"{}\"", path.display()))?;
+    Ok(())
+}
+
+pub fn clear_session(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Failed to remove \"{}\"", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use crate::config::SeatStrategy;
+use crate::section_pattern::SectionPattern;
+use crate::ucam_cloud_api::CourseSection;
+
+/// Decides which of a course's selectable sections is selected.
+pub trait SelectionStrategy: std::fmt::Debug + Send + Sync {
+    /// The section of `sections` to select, only ones matching a `preferred` pattern count.
+    fn choose<'a>(
+        &self,
+        sections: &[&'a CourseSection],
+        preferred: &[SectionPattern],
+    ) -> Option<&'a CourseSection>;
+
+    /// Every section [`SelectionStrategy::choose`] would pick, in the order it picks them, for
+    /// racing the preferred sections.
+    fn rank<'a>(
+        &self,
+        sections: &[&'a CourseSection],
+        preferred: &[SectionPattern],
+    ) -> Vec<&'a CourseSection> {
+        let mut left = sections.to_vec();
+        let mut ranked = Vec::new();
+        while let Some(chosen) = self.choose(&left, preferred) {
+            left.retain(|s| s.section_id != chosen.section_id);
+            ranked.push(chosen);
+        }
+        ranked
+    }
+}
+
+/// The order of the preferred sections in the config.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PreferredOrderStrategy;
+
+impl SelectionStrategy for PreferredOrderStrategy {
+    fn choose<'a>(
+        &self,
+        sections: &[&'a CourseSection],
+        preferred: &[SectionPattern],
+    ) -> Option<&'a CourseSection> {
+        preferred.iter().find_map(|pattern| {
+            sections
+                .iter()
+                .find(|s| pattern.matches(&s.section_name))
+                .copied()
+        })
+    }
+}
+
+/// The preferred section with the most free seats, ties in the config order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MostSeatsStrategy;
+
+impl SelectionStrategy for MostSeatsStrategy {
+    fn choose<'a>(
+        &self,
+        sections: &[&'a CourseSection],
+        preferred: &[SectionPattern],
+    ) -> Option<&'a CourseSection> {
+        // `min_by_key` keeps the first of the ties.
+        PreferredOrderStrategy
+            .rank(sections, preferred)
+            .into_iter()
+            .min_by_key(|s| std::cmp::Reverse(s.available_seats()))
+    }
+}
+
+/// The preferred section with the shortest waitlist, ties in the config order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LeastWaitlistStrategy;
+
+impl SelectionStrategy for LeastWaitlistStrategy {
+    fn choose<'a>(
+        &self,
+        sections: &[&'a CourseSection],
+        preferred: &[SectionPattern],
+    ) -> Option<&'a CourseSection> {
+        PreferredOrderStrategy
+            .rank(sections, preferred)
+            .into_iter()
+            .min_by_key(|s| s.waitlist_count)
+    }
+}
+
+impl SeatStrategy {
+    /// The strategy of the configured `prefer`.
+    pub fn strategy(self) -> Arc<dyn SelectionStrategy> {
+        match self {
+            SeatStrategy::ConfigOrder => Arc::new(PreferredOrderStrategy),
+            SeatStrategy::MostSeats => Arc::new(MostSeatsStrategy),
+            SeatStrategy::LeastWaitlist => Arc::new(LeastWaitlistStrategy),
+        }
+    }
+}
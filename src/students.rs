@@ -0,0 +1,71 @@
+use anyhow::{Context, Result};
+
+/// A student of a `--students` CSV, whose rows are `student_id,password,config_path`.
+pub struct StudentRow {
+    /// Line of the row in the CSV.
+    pub line: u64,
+    pub student_id: String,
+    pub password: String,
+    pub config_path: String,
+}
+
+impl std::fmt::Debug for StudentRow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StudentRow")
+            .field("line", &self.line)
+            .field("student_id", &self.student_id)
+            .field("password", &crate::ucam_cloud_api::REDACTED)
+            .field("config_path", &self.config_path)
+            .finish()
+    }
+}
+
+/// Reads the students of the CSV at `path`, skipping a `student_id,password,config_path` header
+/// if there's one. Malformed rows are left out and described instead, without their contents
+/// since they may hold a password.
+pub fn read_students(path: &str) -> Result<(Vec<StudentRow>, Vec<String>)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .with_context(|| format!("Failed to open the students CSV \"{path}\""))?;
+    let mut students = Vec::new();
+    let mut malformed = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map_or(i as u64 + 1, |p| p.line());
+                malformed.push(format!("line {line}: unreadable row"));
+                continue;
+            }
+        };
+        let line = record.position().map_or(i as u64 + 1, |p| p.line());
+        if i == 0
+            && record
+                .get(0)
+                .is_some_and(|c| c.eq_ignore_ascii_case("student_id"))
+        {
+            continue;
+        }
+        match record.iter().collect::<Vec<_>>().as_slice() {
+            [student_id, password, config_path]
+                if !student_id.is_empty() && !password.is_empty() && !config_path.is_empty() =>
+            {
+                students.push(StudentRow {
+                    line,
+                    student_id: student_id.to_string(),
+                    password: password.to_string(),
+                    config_path: config_path.to_string(),
+                });
+            }
+            [_, _, _] => malformed.push(format!("line {line}: empty column")),
+            columns => malformed.push(format!(
+                "line {line}: expected 3 columns(student_id,password,config_path), got {}",
+                columns.len()
+            )),
+        }
+    }
+    Ok((students, malformed))
+}
@@ -0,0 +1,245 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use futures_util::StreamExt;
+use ratatui::{
+    Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    widgets::{Block, Paragraph, Row, Table, TableState},
+};
+use tokio::sync::mpsc;
+use uiu_auto_section_selection::section_api::SectionApi;
+use uiu_auto_section_selection::ucam_cloud_api::{ApiClient, CourseSection, CourseSections};
+use uiu_auto_section_selection::watch;
+
+enum Update {
+    Sections(usize, Result<CourseSections, String>),
+    Key(KeyCode),
+    Redraw,
+    Status(String),
+}
+
+struct CourseRow {
+    course_code: String,
+    course_name: String,
+    /// `None` until the first poll of the course finishes.
+    sections: Option<Result<CourseSections, String>>,
+}
+
+struct App {
+    courses: Vec<CourseRow>,
+    table: TableState,
+    status: String,
+}
+
+impl App {
+    /// The course and the section, if there's one, of every table row.
+    fn rows(&self) -> Vec<(usize, Option<usize>)> {
+        let mut rows = Vec::new();
+        for (i, course) in self.courses.iter().enumerate() {
+            match &course.sections {
+                Some(Ok(sections)) if !sections.sections.is_empty() => {
+                    rows.extend((0..sections.sections.len()).map(|j| (i, Some(j))));
+                }
+                _ => rows.push((i, None)),
+            }
+        }
+        rows
+    }
+
+    fn selected_section(&self) -> Option<(&CourseRow, &CourseSection)> {
+        let (i, j) = *self.rows().get(self.table.selected()?)?;
+        let course = &self.courses[i];
+        match &course.sections {
+            Some(Ok(sections)) => Some((course, sections.sections.get(j?)?)),
+            _ => None,
+        }
+    }
+}
+
+/// Shows the sections of `courses`(code and name pairs), polled every `interval`, until `q` is
+/// pressed. `s` selects the highlighted section, only logging it when `dry_run`.
+pub async fn run(
+    client: ApiClient,
+    student_id: String,
+    courses: Vec<(String, String)>,
+    interval: Duration,
+    dry_run: bool,
+) -> Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut pollers = tokio::task::JoinSet::new();
+    for (i, (course_code, _)) in courses.iter().enumerate() {
+        pollers.spawn(poll_course(
+            client.clone(),
+            i,
+            course_code.clone(),
+            student_id.clone(),
+            interval,
+            tx.clone(),
+        ));
+    }
+    let key_tx = tx.clone();
+    // Reading the terminal events blocks, it's fine to leave the thread behind on exit.
+    std::thread::spawn(move || {
+        loop {
+            let update = match event::read() {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => Update::Key(key.code),
+                Ok(_) => Update::Redraw,
+                Err(_) => return,
+            };
+            if key_tx.send(update).is_err() {
+                return;
+            }
+        }
+    });
+
+    let mut app = App {
+        courses: courses
+            .into_iter()
+            .map(|(course_code, course_name)| CourseRow {
+                course_code,
+                course_name,
+                sections: None,
+            })
+            .collect(),
+        table: TableState::default().with_selected(0),
+        status: String::new(),
+    };
+    let mut terminal = ratatui::init();
+    let result = loop {
+        if let Err(e) = terminal.draw(|frame| draw(frame, &mut app)) {
+            break Err(e.into());
+        }
+        let Some(update) = rx.recv().await else {
+            break Ok(());
+        };
+        match update {
+            Update::Sections(i, sections) => app.courses[i].sections = Some(sections),
+            Update::Status(status) => app.status = status,
+            Update::Redraw => {}
+            Update::Key(KeyCode::Char('q') | KeyCode::Esc) => break Ok(()),
+            Update::Key(KeyCode::Down | KeyCode::Char('j')) => {
+                let last = app.rows().len().saturating_sub(1);
+                let next = app.table.selected().map_or(0, |i| (i + 1).min(last));
+                app.table.select(Some(next));
+            }
+            Update::Key(KeyCode::Up | KeyCode::Char('k')) => {
+                let previous = app.table.selected().map_or(0, |i| i.saturating_sub(1));
+                app.table.select(Some(previous));
+            }
+            Update::Key(KeyCode::Char('s') | KeyCode::Enter) => {
+                let Some((course, section)) = app.selected_section() else {
+                    continue;
+                };
+                let (course_code, section_id) = (course.course_code.clone(), section.section_id);
+                let description =
+                    format!("section {} of {}", section.section_name, course.course_name);
+                if dry_run {
+                    app.status = format!("Dry run, would have selected {description}");
+                    continue;
+                }
+                app.status = format!("Selecting {description}...");
+                let client = client.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let status = match client.select_section(&course_code, section_id).await {
+                        Ok(()) => format!("Selected {description}"),
+                        Err(e) => format!("Selecting {description} failed: {e}"),
+                    };
+                    _ = tx.send(Update::Status(status));
+                });
+            }
+            Update::Key(_) => {}
+        }
+    };
+    ratatui::restore();
+    result
+}
+
+/// Sends every snapshot of a course's sections, polling it again after an error.
+async fn poll_course(
+    client: ApiClient,
+    index: usize,
+    course_code: String,
+    student_id: String,
+    interval: Duration,
+    tx: mpsc::UnboundedSender<Update>,
+) {
+    loop {
+        let mut sections = std::pin::pin!(watch::subscribe_sections(
+            client.clone(),
+            course_code.clone(),
+            student_id.clone(),
+            interval,
+        ));
+        while let Some(result) = sections.next().await {
+            let update = Update::Sections(index, result.map_err(|e| e.to_string()));
+            if tx.send(update).is_err() {
+                return;
+            }
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+fn draw(frame: &mut Frame, app: &mut App) {
+    let [table_area, status_area] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+    let rows = app.rows().into_iter().map(|(i, j)| {
+        let course = &app.courses[i];
+        let title = match j {
+            Some(0) | None => format!("{} {}", course.course_code, course.course_name),
+            Some(_) => String::new(),
+        };
+        let section = match (&course.sections, j) {
+            (Some(Ok(sections)), Some(j)) => &sections.sections[j],
+            (Some(Err(e)), _) => {
+                return Row::new([title, format!("Polling failed: {e}")])
+                    .style(Style::new().fg(Color::Yellow));
+            }
+            (Some(Ok(_)), _) => return Row::new([title, "No sections yet".to_string()]),
+            (None, _) => return Row::new([title, "Loading...".to_string()]),
+        };
+        let (status, color) = if section.is_enrolled {
+            ("Enrolled", Color::Cyan)
+        } else if !section.is_selectable() {
+            ("Closed", Color::DarkGray)
+        } else if section.available_seats() == 0 {
+            ("Full", Color::Red)
+        } else {
+            ("Open", Color::Green)
+        };
+        Row::new([
+            title,
+            section.section_name.clone(),
+            format!("{}/{}", section.seats_taken, section.total_seats),
+            section.waitlist_count.to_string(),
+            section.faculty_name.clone(),
+            status.to_string(),
+        ])
+        .style(Style::new().fg(color))
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Fill(2),
+            Constraint::Length(10),
+            Constraint::Length(9),
+            Constraint::Length(8),
+            Constraint::Fill(1),
+            Constraint::Length(8),
+        ],
+    )
+    .header(
+        Row::new([
+            "Course", "Section", "Seats", "Waitlist", "Faculty", "Status",
+        ])
+        .style(Style::new().add_modifier(Modifier::BOLD)),
+    )
+    .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+    .block(Block::bordered().title(" Sections, ↑/↓ move, s select, q quit "));
+    frame.render_stateful_widget(table, table_area, &mut app.table);
+    frame.render_widget(Paragraph::new(app.status.as_str()), status_area);
+}
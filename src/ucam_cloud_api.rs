@@ -1,18 +1,23 @@
 use std::{
     collections::HashMap,
     fmt::Debug,
-    io::{BufRead, Write},
-    sync::Arc,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
 };
 
-use anyhow::Result;
-use chrono::{DateTime, Utc};
-use reqwest::header::{self, HeaderMap, HeaderValue};
-use tokio::{self, fs};
+use chrono::{DateTime, TimeDelta, Utc};
+use reqwest::{
+    IntoUrl, Method, RequestBuilder, StatusCode,
+    header::{self, HeaderMap, HeaderValue},
+};
+use serde::de::DeserializeOwned;
 use ua_generator::ua::spoof_ua;
 
 use crate::macros::concat_sstr;
+use crate::schedule::{ClassSlot, parse_schedule};
+use crate::section_api::SectionApi;
 
+/// A course of the catalog, see [`ApiClient::fetch_all_courses`].
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct CourseGeneralInfo {
     pub id: String,
@@ -24,6 +29,7 @@ pub struct CourseGeneralInfo {
     pub preadvised: bool,
 }
 
+/// A course the student is enrolled in, see [`ApiClient::fetch_my_courses`].
 #[derive(Debug, serde::Deserialize)]
 pub struct MyCourseInfo {
     pub id: String,
@@ -44,6 +50,8 @@ pub struct MyCoursesAndInfo {
     pub courses: Vec<MyCourseInfo>,
 }
 
+/// A course with its sections as the student sees them, see
+/// [`ApiClient::fetch_course_data_as_student`].
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct CourseData {
     pub user_id: String,
@@ -70,6 +78,7 @@ pub struct CourseInfo {
     pub preadvised: bool,
 }
 
+/// A section of a [`CourseData`].
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct Section {
     pub id: String,
@@ -88,15 +97,44 @@ pub struct Section {
     pub quotas: Vec<Quota>,
     pub is_mapped: bool,
     pub original_course: Option<CourseInfo>,
-    /* REDACTED FIELDS
+    /// What the section clashes with in the student's schedule, see `have_conflict`.
+    #[serde(default)]
     pub conflict_with: String,
+    /// Whether the server says the section clashes with the student's schedule.
+    #[serde(default)]
     pub have_conflict: bool,
-     */
     pub can_not_remove: bool,
     pub already_taken: bool,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+impl Section {
+    pub fn class_slots(&self) -> anyhow::Result<Vec<ClassSlot>> {
+        parse_schedule(&self.schedule)
+    }
+}
+
+/// Free seats of the section the department can still take, see [`department_seats`].
+pub fn available_for_department(section: &Section, dept: &str) -> i32 {
+    department_seats(&section.quotas, section.available_seats, dept)
+}
+
+/// Caps `free_seats` by the department's quota, matched by name or id ignoring case. Without any
+/// quotas every seat is open to every department, with quotas but none for the department it
+/// can't take any.
+fn department_seats(quotas: &[Quota], free_seats: i32, dept: &str) -> i32 {
+    if quotas.is_empty() {
+        return free_seats.max(0);
+    }
+    quotas
+        .iter()
+        .find(|q| {
+            q.department_name.eq_ignore_ascii_case(dept)
+                || q.department_id.eq_ignore_ascii_case(dept)
+        })
+        .map_or(0, |q| q.available.min(free_seats).max(0))
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Quota {
     pub id: String,
     pub department_id: String,
@@ -113,20 +151,149 @@ pub struct CacheInfo {
     pub expires_at: String,
 }
 
-#[derive(Debug, serde::Serialize)]
+impl CacheInfo {
+    /// How long until the server serves fresh data again, `None` when it isn't cached or the
+    /// expiry can't be read.
+    pub fn expires_in(&self, now: DateTime<Utc>) -> Option<Duration> {
+        if !self.is_cached {
+            return None;
+        }
+        let expires_at = DateTime::parse_from_rfc3339(&self.expires_at).ok()?;
+        (expires_at.with_timezone(&Utc) - now).to_std().ok()
+    }
+}
+
+/// Stands in for secrets in `Debug` output, logs get pasted around when asking for help.
+pub const REDACTED: &str = "***";
+
+/// Credentials of [`ApiClient::login`].
+#[derive(serde::Serialize)]
 pub struct LoginRequest {
     pub user_id: String,
     pub password: String,
     pub logout_other_sessions: bool,
 }
 
+impl Debug for LoginRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoginRequest")
+            .field("user_id", &self.user_id)
+            .field("password", &REDACTED)
+            .field("logout_other_sessions", &self.logout_other_sessions)
+            .finish()
+    }
+}
+
+/// What [`ApiClient::post_course_action`] does with a section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SectionAction {
+    Select,
+    Drop,
+    /// Joins the waitlist of a full section.
+    Waitlist,
+}
+
+/// Body of [`ApiClient::post_course_action`].
 #[derive(Debug, serde::Serialize)]
 pub struct SectionActionRequest {
     pub section_id: u64,
-    pub action: String,
+    pub action: SectionAction,
     pub parent_course_code: String,
 }
 
+/// Every way a request of [`ApiClient`] can fail.
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("Login failed: {0}")]
+    LoginFailed(String),
+    #[error("Invalid or expired access token")]
+    InvalidToken,
+    #[error("Selection window closed: {0}")]
+    WindowClosed(String),
+    #[error("Seat already taken: {0}")]
+    SeatTaken(String),
+    /// The student is enrolled in the course already, eg. by an earlier run.
+    #[error("Already enrolled: {0}")]
+    AlreadyEnrolled(String),
+    #[error("{context} failed: {message}")]
+    Failed {
+        context: &'static str,
+        message: String,
+    },
+    #[error("Data parsing failed!")]
+    MissingData,
+    #[error("Network error: {0}")]
+    Network(#[from] reqwest::Error),
+    #[error("Parsing error: {0}")]
+    Parse(#[from] serde_json::Error),
+    /// A response that isn't the expected JSON, eg. an HTML page of a gateway or firewall.
+    #[error(
+        "{context} got a malformed response({status}): {source}, the body starts with {snippet:?}"
+    )]
+    MalformedResponse {
+        context: &'static str,
+        status: StatusCode,
+        source: serde_json::Error,
+        snippet: String,
+    },
+    #[error("Invalid header value: {0}")]
+    InvalidHeader(#[from] header::InvalidHeaderValue),
+    #[error("Invalid proxy URL: {0}")]
+    InvalidProxy(String),
+    /// A `5xx` response, its body is often an HTML error page rather than JSON.
+    #[error("Server error: {status}")]
+    ServerError { status: StatusCode },
+}
+
+impl ApiError {
+    /// Whether the request timed out, retrying it later may well work.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, ApiError::Network(e) if e.is_timeout())
+    }
+
+    /// Whether the server failed to answer the request, eg. under peak load.
+    pub fn is_server_error(&self) -> bool {
+        matches!(self, ApiError::ServerError { .. })
+    }
+
+    /// Whether the error is a network hiccup or a server failure worth retrying right away.
+    /// Authentication failures and other `4xx` responses never are, retrying them only gets the
+    /// same answer.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ApiError::Network(e) => {
+                !e.status().is_some_and(|status| status.is_client_error())
+                    && (e.is_timeout() || e.is_connect() || e.is_request() || e.is_body())
+            }
+            ApiError::ServerError { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Classifies the message of an unsuccessful response, this is the only place matching on
+    /// the server's wording.
+    fn from_message(context: &'static str, message: String) -> Self {
+        let lower = message.to_lowercase();
+        if lower.contains("invalid token") || lower.contains("token expired") {
+            ApiError::InvalidToken
+        } else if lower.contains("already enrolled")
+            || lower.contains("already registered")
+            || lower.contains("duplicate")
+        {
+            ApiError::AlreadyEnrolled(message)
+        } else if lower.contains("closed") || lower.contains("not open") {
+            ApiError::WindowClosed(message)
+        } else if lower.contains("no seat") || lower.contains("full") {
+            ApiError::SeatTaken(message)
+        } else {
+            ApiError::Failed { context, message }
+        }
+    }
+}
+
+pub type Result<T, E = ApiError> = std::result::Result<T, E>;
+
 #[derive(serde::Deserialize, Debug)]
 struct Response<T: Sized + Debug> {
     status: String,
@@ -134,12 +301,63 @@ struct Response<T: Sized + Debug> {
     message: Option<String>,
 }
 
-#[derive(Debug, serde::Deserialize)]
-struct Login {
-    access_token: String,
-    refresh_token: String,
-    access_token_expires_at: DateTime<Utc>,
-    refresh_token_expires_at: DateTime<Utc>,
+/// Tokens of a logged in session.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Login {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub access_token_expires_at: DateTime<Utc>,
+    pub refresh_token_expires_at: DateTime<Utc>,
+}
+
+impl Debug for Login {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Login")
+            .field("access_token", &REDACTED)
+            .field("refresh_token", &REDACTED)
+            .field("access_token_expires_at", &self.access_token_expires_at)
+            .field("refresh_token_expires_at", &self.refresh_token_expires_at)
+            .finish()
+    }
+}
+
+/// How long before its expiry an access token is considered expired, so it gets refreshed
+/// before any request can fail with it.
+pub const TOKEN_EXPIRY_MARGIN_SECS: i64 = 30;
+
+#[derive(Clone)]
+pub struct TokenState {
+    pub access_token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Debug for TokenState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenState")
+            .field("access_token", &REDACTED)
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+impl TokenState {
+    pub fn expires_soon(&self) -> bool {
+        Utc::now() + TimeDelta::seconds(TOKEN_EXPIRY_MARGIN_SECS) >= self.expires_at
+    }
+}
+
+impl From<&Login> for TokenState {
+    fn from(login: &Login) -> Self {
+        Self {
+            access_token: login.access_token.clone(),
+            expires_at: login.access_token_expires_at,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+struct RefreshRequest<'a> {
+    refresh_token: &'a str,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -155,6 +373,7 @@ pub struct PreadviceCourse {
     pub updated_at: DateTime<Utc>,
 }
 
+/// The preadvised courses of the student, see [`ApiClient::fetch_preadvised_courses`].
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct PreadviceCourses {
     pub user_id: String,
@@ -164,7 +383,8 @@ pub struct PreadviceCourses {
     pub total_credits: usize,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+/// A section of a [`CourseSections`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct CourseSection {
     pub section_id: u64,
     pub section_name: String,
@@ -173,9 +393,63 @@ pub struct CourseSection {
     pub is_enrolled: bool,
     pub faculty_name: String,
     pub faculty_email: String,
+    #[serde(default)]
+    pub faculty_code: String,
+    #[serde(default)]
+    pub quotas: Vec<Quota>,
+    /// Day -> class time ranges (eg. `"08:30-09:50"`).
+    #[serde(default)]
+    pub schedule: HashMap<String, String>,
+    #[serde(default)]
+    pub waitlist_count: usize,
+    /// Missing from older responses, the section is assumed to be open then.
+    #[serde(default = "default_true")]
+    pub can_enroll: bool,
+    #[serde(default = "default_true")]
+    pub is_active: bool,
+    /// What the section clashes with in the student's schedule, see `have_conflict`.
+    #[serde(default)]
+    pub conflict_with: String,
+    /// Whether the server says the section clashes with the student's schedule, more reliable
+    /// than comparing the schedules ourselves. Missing from older responses.
+    #[serde(default)]
+    pub have_conflict: bool,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+fn default_true() -> bool {
+    true
+}
+
+impl CourseSection {
+    /// Free seats that can actually be taken, capped by the free department quota seats when the
+    /// server reports any quotas, as seats reserved for other departments look free otherwise.
+    pub fn available_seats(&self) -> usize {
+        let free_seats = self.total_seats.saturating_sub(self.seats_taken);
+        if self.quotas.is_empty() {
+            return free_seats;
+        }
+        let free_quota_seats: i32 = self.quotas.iter().map(|q| q.available.max(0)).sum();
+        free_seats.min(free_quota_seats as usize)
+    }
+
+    /// Whether the server would accept a select of this section at all.
+    pub fn is_selectable(&self) -> bool {
+        self.can_enroll && self.is_active
+    }
+
+    /// Free seats the department can still take, see [`department_seats`].
+    pub fn available_for_department(&self, dept: &str) -> usize {
+        let free_seats = self.total_seats.saturating_sub(self.seats_taken);
+        department_seats(&self.quotas, free_seats as i32, dept) as usize
+    }
+
+    pub fn class_slots(&self) -> anyhow::Result<Vec<ClassSlot>> {
+        parse_schedule(&self.schedule)
+    }
+}
+
+/// A preadvised course with its sections, see [`ApiClient::fetch_course_sections`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct CourseSections {
     pub course_code: String,
     pub course_name: String,
@@ -187,38 +461,269 @@ pub struct CourseSections {
     pub section_selection_end_time: DateTime<Utc>,
 }
 
+#[derive(Debug, Clone)]
+pub struct RateLimitPolicy {
+    /// Whether to wait and retry a request answered with `429 Too Many Requests` at all.
+    pub enabled: bool,
+    pub max_retries: u32,
+    /// How long to wait when a `429` response has no usable `Retry-After` header.
+    pub default_wait: Duration,
+    /// Upper bound of the wait, in case the server asks for something unreasonable.
+    pub max_wait: Duration,
+}
+
+impl Default for RateLimitPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_retries: 5,
+            default_wait: Duration::from_secs(5),
+            max_wait: Duration::from_secs(120),
+        }
+    }
+}
+
+impl RateLimitPolicy {
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// How many times a request failing with a transient error is retried, zero disables it.
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled on every further one.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CircuitBreakerPolicy {
+    /// How many requests in a row have to fail before every request is paused, zero disables
+    /// the breaker.
+    pub failure_threshold: u32,
+    /// How long the requests are paused before a single one probes whether the server is back.
+    pub cooldown: Duration,
+}
+
+impl Default for CircuitBreakerPolicy {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BreakerState {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: tokio::time::Instant,
+    },
+    /// A probe request is in flight, the others wait for its outcome.
+    HalfOpen,
+}
+
+/// Pauses every request after repeated failures, so a server in maintenance isn't hammered.
+#[derive(Debug)]
+struct CircuitBreaker {
+    policy: CircuitBreakerPolicy,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    fn new(policy: CircuitBreakerPolicy) -> Self {
+        Self {
+            policy,
+            state: Mutex::new(BreakerState::Closed { failures: 0 }),
+        }
+    }
+
+    /// Waits until a request may be sent, its outcome goes to [`BreakerPermit::record`].
+    async fn acquire(&self) -> BreakerPermit<'_> {
+        let permit = |probe| BreakerPermit {
+            breaker: self,
+            probe,
+            recorded: false,
+        };
+        loop {
+            let wait_until = {
+                let mut state = self.state.lock().unwrap();
+                match *state {
+                    BreakerState::Closed { .. } => return permit(false),
+                    BreakerState::Open { until } if tokio::time::Instant::now() >= until => {
+                        tracing::info!("Circuit breaker half-open, probing the server...");
+                        *state = BreakerState::HalfOpen;
+                        return permit(true);
+                    }
+                    BreakerState::Open { until } => until,
+                    BreakerState::HalfOpen => {
+                        tokio::time::Instant::now() + Duration::from_millis(250)
+                    }
+                }
+            };
+            tokio::time::sleep_until(wait_until).await;
+        }
+    }
+
+    fn record(&self, failed: bool) {
+        if self.policy.failure_threshold == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let open_until = tokio::time::Instant::now() + self.policy.cooldown;
+        match (*state, failed) {
+            (BreakerState::Closed { failures }, true) => {
+                let failures = failures + 1;
+                if failures >= self.policy.failure_threshold {
+                    tracing::warn!(
+                        "Circuit breaker open after {failures} failed requests in a row, pausing \
+                         every request for {}s",
+                        self.policy.cooldown.as_secs()
+                    );
+                    *state = BreakerState::Open { until: open_until };
+                } else {
+                    *state = BreakerState::Closed { failures };
+                }
+            }
+            (BreakerState::Closed { .. }, false) => *state = BreakerState::Closed { failures: 0 },
+            (BreakerState::HalfOpen, true) => {
+                tracing::warn!(
+                    "Circuit breaker open again, the probe failed, pausing every request for {}s",
+                    self.policy.cooldown.as_secs()
+                );
+                *state = BreakerState::Open { until: open_until };
+            }
+            (BreakerState::HalfOpen, false) => {
+                tracing::info!("Circuit breaker closed, the server answered the probe");
+                *state = BreakerState::Closed { failures: 0 };
+            }
+            // Sent before the circuit opened.
+            (BreakerState::Open { .. }, _) => {}
+        }
+    }
+}
+
+/// A request let through by [`CircuitBreaker::acquire`].
+struct BreakerPermit<'a> {
+    breaker: &'a CircuitBreaker,
+    /// Whether the request probes a half-open circuit.
+    probe: bool,
+    recorded: bool,
+}
+
+impl BreakerPermit<'_> {
+    /// Records the outcome of the request.
+    fn record(mut self, failed: bool) {
+        self.recorded = true;
+        self.breaker.record(failed);
+    }
+}
+
+impl Drop for BreakerPermit<'_> {
+    /// A probe dropped before its outcome, eg. a select cancelled by a won race, would leave the
+    /// circuit half-open for good, so it's opened again with the cooldown already over and the
+    /// next request probes instead.
+    fn drop(&mut self) {
+        if !self.probe || self.recorded {
+            return;
+        }
+        let mut state = self.breaker.state.lock().unwrap();
+        if matches!(*state, BreakerState::HalfOpen) {
+            *state = BreakerState::Open {
+                until: tokio::time::Instant::now(),
+            };
+        }
+    }
+}
+
+/// Calls `f` until it succeeds, fails with an error that isn't [transient], or the retries of the
+/// policy run out, backing off between the calls.
+///
+/// [transient]: ApiError::is_transient
+pub async fn with_retry<F, Fut, T>(mut f: F, policy: RetryPolicy) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut retries = 0;
+    loop {
+        match f().await {
+            Err(e) if e.is_transient() && retries < policy.max_retries => {
+                let delay = policy
+                    .base_delay
+                    .saturating_mul(2u32.saturating_pow(retries))
+                    .min(policy.max_delay);
+                retries += 1;
+                tokio::time::sleep(delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
 pub const ORIGIN: &str = "https://m5p10igya2.execute-api.ap-southeast-1.amazonaws.com";
-pub const LOGIN_PATH: &str = "/v3/auth/login";
+const AUTH_PATH: &str = "/v3/auth";
+pub const LOGIN_PATH: &str = concat_sstr!(AUTH_PATH, "/login");
+pub const REFRESH_PATH: &str = concat_sstr!(AUTH_PATH, "/refresh");
+pub const LOGOUT_PATH: &str = concat_sstr!(AUTH_PATH, "/logout");
 pub const PREADVICE_COURSES_PATH: &str = "/v3/users/me/preadvice-courses";
+pub const MY_COURSES_PATH: &str = "/v3/users/me/courses";
 pub const SECTIONS_PATH: &str = "/v3/courses/sections";
 
-pub async fn login_client(login_req: &LoginRequest) -> Result<reqwest::Client> {
-    const URI: &str = concat_sstr!(ORIGIN, LOGIN_PATH);
+pub const LEGACY_ORIGIN: &str = "https://t8kdcntnt1.execute-api.ap-southeast-1.amazonaws.com";
+pub const ALL_COURSES_PATH: &str = "/v1/sections/routine/courses/department/all";
+pub const COURSE_SECTIONS_PATH: &str = "/v1/sections/course";
+const ALL_COURSES_PAGE_SIZE: usize = 100;
 
-    let ua = spoof_ua();
-    let cookie_jar = Arc::new(reqwest::cookie::Jar::default());
-    let client = reqwest::Client::builder()
-        .user_agent(ua)
-        //.cookie_provider(cookie_jar.clone())
-        .build()?;
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
 
-    let result = client.post(URI).json(&login_req).send().await?;
+/// Talks to the Ucam cloud API, cheap to clone. Every clone shares the same connection pool and
+/// access token, so a [`ApiClient::login`] or a refresh through any of them applies to all.
+#[derive(Debug, Clone)]
+pub struct ApiClient {
+    base_url: String,
+    legacy_base_url: String,
+    user_agent: String,
+    /// The API authenticates with the bearer token alone, the jar only keeps any cookie the
+    /// server sets along the way.
+    cookie_jar: Arc<reqwest::cookie::Jar>,
+    proxy: Option<reqwest::Proxy>,
+    http: reqwest::Client,
+    rate_limit: RateLimitPolicy,
+    /// How long a request may take before failing with a timeout error.
+    timeout: Duration,
+    /// Applies to the fetches only, posting a section action twice isn't safe.
+    retry: RetryPolicy,
+    /// Shared by every clone, the server being down affects all of them.
+    breaker: Arc<CircuitBreaker>,
+    /// Sent as the bearer token with every request once logged in.
+    token: Arc<RwLock<Option<TokenState>>>,
+}
 
-    let response = result.json::<Response<Login>>().await?;
-    if response.status != "success" {
-        anyhow::bail!(
-            "Login failed: {:?}",
-            response.message.unwrap_or(response.status)
-        );
-    }
-    let response = response
-        .data
-        .ok_or(anyhow::anyhow!("Data parsing failed!"))?;
+fn build_http(
+    user_agent: &str,
+    cookie_jar: &Arc<reqwest::cookie::Jar>,
+    proxy: Option<&reqwest::Proxy>,
+) -> Result<reqwest::Client> {
     let mut headers = HeaderMap::new();
-    headers.append(
-        header::AUTHORIZATION,
-        HeaderValue::from_str(&format!("Bearer {}", response.access_token))?,
-    );
     headers.append(
         header::ORIGIN,
         HeaderValue::from_static("https://ucamcloud.uiu.ac.bd"),
@@ -228,102 +733,814 @@ pub async fn login_client(login_req: &LoginRequest) -> Result<reqwest::Client> {
         HeaderValue::from_static("https://ucamcloud.uiu.ac.bd/"),
     );
     headers.append(header::ACCEPT, HeaderValue::from_static("*/*"));
-
-    return Ok(reqwest::Client::builder()
-        .user_agent(ua)
-        //.cookie_provider(cookie_jar)
+    // Sends `Accept-Encoding: gzip` and decompresses the gzipped responses, the ones the server
+    // sends uncompressed are read as they are.
+    let mut builder = reqwest::Client::builder()
+        .user_agent(user_agent)
+        .cookie_provider(cookie_jar.clone())
         .default_headers(headers)
-        .build()?);
-}
-
-pub async fn fetch_all_courses(client: &reqwest::Client) -> Result<Vec<CourseGeneralInfo>> {
-    todo!("Not implemeneted yet!");
-    // let result = client.get("https://t8kdcntnt1.execute-api.ap-southeast-1.amazonaws.com/v1/sections/routine/courses/department/endpoint-url-does-matter-at-all-so-whatever").send().await?;
-    // let response: Response<Vec<CourseGeneralInfo>> = result.json().await?;
-    // if response.status != "success" {
-    //     anyhow::bail!(
-    //         "Fetch all courses failed: {:?}",
-    //         response.message.unwrap_or(response.status)
-    //     );
-    // }
-    // Ok(response
-    //     .data
-    //     .ok_or(anyhow::anyhow!("Data parsing failed!"))?)
-}
-
-pub async fn fetch_preadvised_courses(client: &reqwest::Client) -> Result<PreadviceCourses> {
-    const URI: &str = concat_sstr!(ORIGIN, PREADVICE_COURSES_PATH);
-    let result = client.get(URI).send().await?;
-    let response: Response<PreadviceCourses> = result.json().await?;
-    if response.status != "success" {
-        anyhow::bail!(
-            "Fetch preadvised courses failed: {:?}",
-            response.message.unwrap_or(response.status)
+        .gzip(true);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(proxy.clone());
+    }
+    Ok(builder.build()?)
+}
+
+impl ApiClient {
+    pub fn new() -> Result<Self> {
+        Self::with_base_url(ORIGIN)
+    }
+
+    /// Points the client at another server, eg. a mock one.
+    pub fn with_base_url(base_url: impl Into<String>) -> Result<Self> {
+        let user_agent = spoof_ua().to_string();
+        let cookie_jar = Arc::new(reqwest::cookie::Jar::default());
+        Ok(Self {
+            base_url: base_url.into(),
+            legacy_base_url: LEGACY_ORIGIN.to_string(),
+            http: build_http(&user_agent, &cookie_jar, None)?,
+            user_agent,
+            cookie_jar,
+            proxy: None,
+            rate_limit: RateLimitPolicy::default(),
+            timeout: DEFAULT_TIMEOUT,
+            retry: RetryPolicy::default(),
+            breaker: Arc::new(CircuitBreaker::new(CircuitBreakerPolicy::default())),
+            token: Arc::default(),
+        })
+    }
+
+    /// Sends `user_agent` instead of the spoofed one picked by [`ApiClient::new`], make sure to
+    /// call it before logging in so the whole session uses the same one.
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Result<Self> {
+        self.user_agent = user_agent.into();
+        self.http = build_http(&self.user_agent, &self.cookie_jar, self.proxy.as_ref())?;
+        Ok(self)
+    }
+
+    /// Routes every request through `proxy_url`, an `http://`, `https://`, `socks5://` or
+    /// `socks5h://` one.
+    pub fn with_proxy(mut self, proxy_url: &str) -> Result<Self> {
+        let scheme = proxy_url.split_once("://").map(|(scheme, _)| scheme);
+        if !scheme.is_some_and(|scheme| {
+            ["http", "https", "socks5", "socks5h"]
+                .iter()
+                .any(|s| scheme.eq_ignore_ascii_case(s))
+        }) {
+            return Err(ApiError::InvalidProxy(
+                "expected an http://, https://, socks5:// or socks5h:// URL".to_string(),
+            ));
+        }
+        let proxy = reqwest::Proxy::all(proxy_url)
+            .map_err(|e| ApiError::InvalidProxy(e.without_url().to_string()))?;
+        self.http = build_http(&self.user_agent, &self.cookie_jar, Some(&proxy))?;
+        self.proxy = Some(proxy);
+        Ok(self)
+    }
+
+    /// Points the endpoints which still live on the older API at another server.
+    pub fn with_legacy_base_url(mut self, legacy_base_url: impl Into<String>) -> Self {
+        self.legacy_base_url = legacy_base_url.into();
+        self
+    }
+
+    pub fn with_rate_limit(mut self, rate_limit: RateLimitPolicy) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_circuit_breaker(mut self, policy: CircuitBreakerPolicy) -> Self {
+        self.breaker = Arc::new(CircuitBreaker::new(policy));
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url)
+    }
+
+    /// The access token every clone of this client currently sends.
+    pub fn token(&self) -> Option<TokenState> {
+        self.token.read().unwrap().clone()
+    }
+
+    /// Makes every clone of this client send the login's access token from now on.
+    pub fn set_token(&self, login: &Login) {
+        *self.token.write().unwrap() = Some(TokenState::from(login));
+    }
+
+    /// Starts a request carrying the current access token, if there's one. The bearer header is
+    /// marked sensitive, so it stays out of the `Debug` output of the request.
+    fn request(&self, method: Method, url: impl IntoUrl) -> RequestBuilder {
+        let request = self.http.request(method, url).timeout(self.timeout);
+        match self.token.read().unwrap().as_ref() {
+            Some(token) => request.bearer_auth(&token.access_token),
+            None => request,
+        }
+    }
+
+    fn get(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.request(Method::GET, url)
+    }
+
+    fn post(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.request(Method::POST, url)
+    }
+
+    pub async fn login(&self, login_req: &LoginRequest) -> Result<Login> {
+        let request = self
+            .http
+            .post(self.url(LOGIN_PATH))
+            .timeout(self.timeout)
+            .json(&login_req);
+        let result = send_timed(request).await?;
+        let login = read_response(result, "Login")
+            .await
+            .map_err(|e| match e {
+                ApiError::Failed { message, .. } => ApiError::LoginFailed(message),
+                e => e,
+            })?
+            .ok_or(ApiError::MissingData)?;
+        self.set_token(&login);
+        Ok(login)
+    }
+
+    /// Gets a new access token without re-submitting the password, every clone of this client
+    /// uses it right away.
+    pub async fn refresh_access_token(&self, refresh_token: &str) -> Result<Login> {
+        let request = self
+            .http
+            .post(self.url(REFRESH_PATH))
+            .timeout(self.timeout)
+            .json(&RefreshRequest { refresh_token });
+        let result = send_timed(request).await?;
+        let login: Login = read_response(result, "Refreshing access token")
+            .await?
+            .ok_or(ApiError::MissingData)?;
+        self.set_token(&login);
+        Ok(login)
+    }
+
+    /// Sends `request` once the circuit breaker lets it through, a network error or a `5xx`
+    /// response counting as a failure of the server.
+    async fn send_guarded(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let permit = self.breaker.acquire().await;
+        let result = send_timed(request).await;
+        let failed = match &result {
+            Ok(response) => response.status().is_server_error(),
+            Err(e) => !e.status().is_some_and(|status| status.is_client_error()),
+        };
+        permit.record(failed);
+        Ok(result?)
+    }
+
+    /// Sends the request, waiting out and retrying `429 Too Many Requests` responses as the
+    /// policy allows. The last response is returned as is once the retries run out.
+    async fn send_with_rate_limit(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        let policy = &self.rate_limit;
+        let mut retries = 0;
+        loop {
+            // Requests with streaming bodies can't be retried, these APIs never send one though.
+            let Some(attempt) = request.try_clone() else {
+                return self.send_guarded(request).await;
+            };
+            let response = self.send_guarded(attempt).await?;
+            if !policy.enabled
+                || response.status() != StatusCode::TOO_MANY_REQUESTS
+                || retries >= policy.max_retries
+            {
+                return Ok(response);
+            }
+            retries += 1;
+            let wait = retry_after(&response)
+                .unwrap_or(policy.default_wait)
+                .min(policy.max_wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Checks that the server answers at all, returning how long it took. Any response but a
+    /// `5xx` counts, the login path answers a GET without logging in.
+    pub async fn health_check(&self) -> Result<Duration> {
+        let started = std::time::Instant::now();
+        let response = self
+            .http
+            .get(self.url(LOGIN_PATH))
+            .timeout(self.timeout)
+            .send()
+            .await?;
+        let elapsed = started.elapsed();
+        let status = response.status();
+        if status.is_server_error() {
+            return Err(ApiError::ServerError { status });
+        }
+        if elapsed >= SLOW_REQUEST_THRESHOLD {
+            tracing::warn!(
+                "The server took {}ms to answer the health check, expect slow requests",
+                elapsed.as_millis()
+            );
+        }
+        Ok(elapsed)
+    }
+
+    /// Ends the session on the server, every clone of this client is unauthenticated afterwards.
+    pub async fn logout(&self) -> Result<()> {
+        let url = self.url(LOGOUT_PATH);
+        let result = self
+            .post_json::<_, serde_json::Value>(&url, &serde_json::json!({}), "Logout")
+            .await;
+        *self.token.write().unwrap() = None;
+        result.map(|_| ())
+    }
+
+    /// Gets `url` and unwraps the data of its response, retrying transient failures.
+    async fn get_json<T: DeserializeOwned + Debug>(
+        &self,
+        url: &str,
+        context: &'static str,
+    ) -> Result<T> {
+        let fetch = || async move {
+            let result = self.send_with_rate_limit(self.get(url)).await?;
+            read_response(result, context)
+                .await?
+                .ok_or(ApiError::MissingData)
+        };
+        with_retry(fetch, self.retry).await
+    }
+
+    /// Posts `body` to `url` exactly once, returning the data of the response if there's any.
+    async fn post_json<B: serde::Serialize + ?Sized, T: DeserializeOwned + Debug>(
+        &self,
+        url: &str,
+        body: &B,
+        context: &'static str,
+    ) -> Result<Option<T>> {
+        let result = self.send_with_rate_limit(self.post(url).json(body)).await?;
+        read_response(result, context).await
+    }
+
+    /// Fetches the whole course catalog, page by page.
+    pub async fn fetch_all_courses(&self) -> Result<Vec<CourseGeneralInfo>> {
+        let mut courses: Vec<CourseGeneralInfo> = Vec::new();
+        for page in 1.. {
+            let url = format!(
+                "{}{ALL_COURSES_PATH}?page={page}&limit={ALL_COURSES_PAGE_SIZE}",
+                self.legacy_base_url
+            );
+            let page_courses: Vec<CourseGeneralInfo> =
+                self.get_json(&url, "Fetch all courses").await?;
+            // The endpoint may ignore the paging parameters and return everything every time.
+            let repeated = page_courses
+                .first()
+                .is_some_and(|first| courses.iter().any(|c| c.id == first.id));
+            if repeated {
+                break;
+            }
+            let last_page = page_courses.len() < ALL_COURSES_PAGE_SIZE;
+            courses.extend(page_courses);
+            if last_page {
+                break;
+            }
+        }
+        Ok(courses)
+    }
+
+    pub async fn fetch_preadvised_courses(&self) -> Result<PreadviceCourses> {
+        let url = self.url(PREADVICE_COURSES_PATH);
+        self.get_json(&url, "Fetch preadvised courses").await
+    }
+
+    /// The courses the student is enrolled in, with the trimester and the status of each.
+    pub async fn fetch_my_courses(&self) -> Result<MyCoursesAndInfo> {
+        let url = self.url(MY_COURSES_PATH);
+        self.get_json(&url, "Fetch my courses").await
+    }
+
+    pub async fn fetch_course_sections(
+        &self,
+        course_id: &str,
+        student_id: &str,
+    ) -> Result<CourseSections> {
+        let url = format!(
+            "{}/{course_id}?student_id={student_id}",
+            self.url(SECTIONS_PATH)
         );
+        self.get_json(&url, "Fetch course routine").await
     }
-    Ok(response
-        .data
-        .ok_or(anyhow::anyhow!("Data parsing failed!"))?)
-}
-
-pub async fn fetch_course_sections(
-    client: &reqwest::Client,
-    course_id: &str,
-    student_id: &str,
-) -> Result<CourseSections> {
-    //todo!("Not implemeneted yet!");
-    const URI: &str = concat_sstr!(ORIGIN, SECTIONS_PATH);
-    let result = client
-        .get(format!("{URI}/{course_id}?student_id={student_id}"))
-        .send()
-        .await?;
-    let response: Response<CourseSections> = result.json().await?;
-    if response.status != "success" {
-        anyhow::bail!(
-            "Fetch course routine failed: {:?}",
-            response.message.unwrap_or(response.status)
+
+    pub async fn fetch_course_data_as_student(&self, course_id: &str) -> Result<CourseData> {
+        let url = format!(
+            "{}{COURSE_SECTIONS_PATH}/{course_id}/student",
+            self.legacy_base_url
         );
+        self.get_json(&url, "Get course info").await
+    }
+
+    pub async fn post_course_action(
+        &self,
+        course_id: &str,
+        action: &SectionActionRequest,
+    ) -> Result<()> {
+        let url = format!("{}/{course_id}/select", self.url(SECTIONS_PATH));
+        self.post_json::<_, serde_json::Value>(&url, action, "Course section action")
+            .await?;
+        Ok(())
+    }
+
+    /// Moves from one section of a course to another, only if the target section has a free
+    /// seat. The original section is selected again if selecting the target one fails after
+    /// dropping it.
+    pub async fn swap_section(
+        &self,
+        course_code: &str,
+        student_id: &str,
+        from_section_id: u64,
+        to_section_id: u64,
+    ) -> Result<SwapOutcome> {
+        let course_info = self.fetch_course_sections(course_code, student_id).await?;
+        let find_section = |section_id: u64| {
+            course_info
+                .sections
+                .iter()
+                .find(|s| s.section_id == section_id)
+                .ok_or_else(|| ApiError::Failed {
+                    context: "Swap section",
+                    message: format!("Section {section_id} not found in course {course_code}"),
+                })
+        };
+        if !find_section(from_section_id)?.is_enrolled {
+            return Err(ApiError::Failed {
+                context: "Swap section",
+                message: format!(
+                    "Not enrolled in section {from_section_id} of course {course_code}"
+                ),
+            });
+        }
+        let to_section = find_section(to_section_id)?;
+        if to_section.available_seats() == 0 {
+            return Ok(SwapOutcome::TargetFull);
+        }
+
+        self.drop_section(course_code, from_section_id).await?;
+        let Err(select_err) = self.select_section(course_code, to_section_id).await else {
+            return Ok(SwapOutcome::Swapped);
+        };
+        match self.select_section(course_code, from_section_id).await {
+            Ok(()) => Ok(SwapOutcome::RolledBack {
+                reason: format!("{select_err}"),
+            }),
+            Err(rollback_err) => Ok(SwapOutcome::Unenrolled {
+                reason: format!("{select_err}, rolling back failed: {rollback_err}"),
+            }),
+        }
+    }
+}
+
+/// Result of [`ApiClient::swap_section`].
+#[derive(Debug)]
+pub enum SwapOutcome {
+    /// Enrolled in the target section now.
+    Swapped,
+    /// The target section had no free seat, nothing was changed.
+    TargetFull,
+    /// Selecting the target section failed, enrolled in the original section again.
+    RolledBack { reason: String },
+    /// Both selecting the target section and re-selecting the original one failed, enrolled in
+    /// neither of them.
+    Unenrolled { reason: String },
+}
+
+/// Requests answered slower than this are logged as a warning, the others at debug level.
+pub const SLOW_REQUEST_THRESHOLD: Duration = Duration::from_secs(5);
+
+/// Sends `request`, logging how long it took by the path of its endpoint.
+async fn send_timed(request: RequestBuilder) -> reqwest::Result<reqwest::Response> {
+    let started = std::time::Instant::now();
+    let result = request.send().await;
+    let elapsed = started.elapsed();
+    let url = match &result {
+        Ok(response) => Some(response.url()),
+        Err(e) => e.url(),
+    };
+    let path = url.map_or("<unknown>", |url| url.path());
+    let millis = elapsed.as_millis();
+    if elapsed >= SLOW_REQUEST_THRESHOLD {
+        tracing::warn!("{path} took {millis}ms to answer, the server is slow");
+    } else {
+        tracing::debug!("{path} took {millis}ms");
+    }
+    result
+}
+
+/// Parses the `Retry-After` header, which is either in seconds or an HTTP date.
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let value = response.headers().get(header::RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let retry_at = DateTime::parse_from_rfc2822(value).ok()?;
+    (retry_at.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+/// How much of a malformed response body is kept for its error.
+const BODY_SNIPPET_LEN: usize = 200;
+
+/// The start of `body` on one line, cut at [`BODY_SNIPPET_LEN`] characters.
+fn body_snippet(body: &str) -> String {
+    let mut snippet: String = body.split_whitespace().collect::<Vec<_>>().join(" ");
+    if let Some((end, _)) = snippet.char_indices().nth(BODY_SNIPPET_LEN) {
+        snippet.truncate(end);
+        snippet.push_str("...");
+    }
+    snippet
+}
+
+/// Parses the `Response<T>` envelope, turning an unsuccessful status into an error. A `5xx`
+/// response isn't parsed at all.
+async fn read_response<T: DeserializeOwned + Debug>(
+    result: reqwest::Response,
+    context: &'static str,
+) -> Result<Option<T>> {
+    let status = result.status();
+    if status.is_server_error() {
+        return Err(ApiError::ServerError { status });
     }
-    Ok(response
-        .data
-        .ok_or(anyhow::anyhow!("Data parsing failed!"))?)
-}
-
-pub async fn fetch_course_data_as_student(
-    client: &reqwest::Client,
-    course_id: &str,
-) -> Result<CourseData> {
-    todo!("Not implemeneted yet!");
-    // let result = client.get(format!("https://t8kdcntnt1.execute-api.ap-southeast-1.amazonaws.com/v1/sections/course/{course_id}/student")).send().await?;
-    // let response: Response<CourseData> = result.json().await?;
-    // if response.status != "success" {
-    //     anyhow::bail!(
-    //         "Get course info failed: {:?}",
-    //         response.message.unwrap_or(response.status)
-    //     );
-    // }
-    // Ok(response
-    //     .data
-    //     .ok_or(anyhow::anyhow!("Data parsing failed!"))?)
-}
-
-pub async fn post_course_action(
-    client: &reqwest::Client,
-    course_id: &str,
-    action: &SectionActionRequest,
-) -> Result<()> {
-    const URI: &str = concat_sstr!(ORIGIN, SECTIONS_PATH);
-    let result = client
-        .post(format!("{URI}/{course_id}/select"))
-        .json(action)
-        .send()
-        .await?;
-    let response: Response<serde_json::Value> = result.json().await?;
+    let body = result.text().await?;
+    let response: Response<T> =
+        serde_json::from_str(&body).map_err(|source| ApiError::MalformedResponse {
+            context,
+            status,
+            source,
+            snippet: body_snippet(&body),
+        })?;
     if response.status != "success" {
-        anyhow::bail!(
-            "Course section action failed: {:?}",
-            response.message.unwrap_or(response.status)
-        );
+        return Err(ApiError::from_message(
+            context,
+            response.message.unwrap_or(response.status),
+        ));
+    }
+    Ok(response.data)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+    use wiremock::matchers::{bearer_token, header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    /// `count` catalog courses, numbered from `first`.
+    fn catalog_page(first: usize, count: usize) -> Value {
+        (first..first + count)
+            .map(|i| {
+                json!({
+                    "id": format!("course-{i}"),
+                    "name": format!("Course {i}"),
+                    "code": format!("CSE {i}"),
+                    "credits": 3,
+                    "description": "",
+                    "department": "CSE",
+                    "preadvised": false
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn catalog_pages_deserialize() {
+        let mut courses = Vec::new();
+        for page in [
+            catalog_page(0, ALL_COURSES_PAGE_SIZE),
+            catalog_page(ALL_COURSES_PAGE_SIZE, 20),
+        ] {
+            let response: Response<Vec<CourseGeneralInfo>> =
+                serde_json::from_value(json!({ "status": "success", "data": page })).unwrap();
+            courses.extend(response.data.unwrap());
+        }
+        assert_eq!(courses.len(), 120);
+        assert_eq!(courses[0].id, "course-0");
+        assert_eq!(courses[119].code, "CSE 119");
+    }
+
+    #[test]
+    fn failed_catalog_page_has_no_data() {
+        let response: Response<Vec<CourseGeneralInfo>> =
+            serde_json::from_value(json!({ "status": "error", "message": "Unauthorized" }))
+                .unwrap();
+        assert!(response.data.is_none());
+        assert_eq!(response.message.as_deref(), Some("Unauthorized"));
+    }
+
+    fn success(data: Value) -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(json!({ "status": "success", "data": data }))
+    }
+
+    fn course_sections() -> Value {
+        json!({
+            "course_code": "CSE 1111",
+            "course_name": "Structured Programming Language",
+            "sections": [
+                {
+                    "section_id": 101,
+                    "section_name": "A",
+                    "total_seats": 40,
+                    "seats_taken": 38,
+                    "is_enrolled": false,
+                    "faculty_name": "Faculty A",
+                    "faculty_email": "a@uiu.ac.bd",
+                    "schedule": { "Saturday": "08:30-09:50" }
+                },
+                {
+                    "section_id": 102,
+                    "section_name": "B",
+                    "total_seats": 40,
+                    "seats_taken": 40,
+                    "is_enrolled": false,
+                    "faculty_name": "Faculty B",
+                    "faculty_email": "b@uiu.ac.bd",
+                    "can_enroll": false
+                }
+            ],
+            "selection_open": true,
+            "running_session": "Fall 2026",
+            "credits": 3,
+            "section_selection_start_time": "2026-10-14T02:00:00Z",
+            "section_selection_end_time": "2026-10-14T10:00:00Z"
+        })
+    }
+
+    fn preadvised_courses() -> Value {
+        json!({
+            "user_id": "0112330000",
+            "running_session": "Fall 2026",
+            "courses": [
+                {
+                    "running_session": "Fall 2026",
+                    "course_code": "CSE 1111",
+                    "course_name": "Structured Programming Language",
+                    "formal_code": "CSE1111",
+                    "ucam_ref": 1111,
+                    "credits": 3,
+                    "last_synced_at": "2026-10-01T00:00:00Z",
+                    "created_at": "2026-09-01T00:00:00Z",
+                    "updated_at": "2026-10-01T00:00:00Z"
+                }
+            ],
+            "total_courses": 1,
+            "total_credits": 3
+        })
+    }
+
+    /// A client for `server` which doesn't retry, so every test sees the first answer.
+    fn client(server: &MockServer) -> ApiClient {
+        ApiClient::with_base_url(server.uri())
+            .unwrap()
+            .with_retry_policy(RetryPolicy {
+                max_retries: 0,
+                ..Default::default()
+            })
+    }
+
+    #[tokio::test]
+    async fn fetches_the_course_sections() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("{SECTIONS_PATH}/CSE1111")))
+            .and(query_param("student_id", "0112330000"))
+            .respond_with(success(course_sections()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let course = client(&server)
+            .fetch_course_sections("CSE1111", "0112330000")
+            .await
+            .unwrap();
+        assert_eq!(course.course_code, "CSE 1111");
+        assert_eq!(course.sections.len(), 2);
+        assert_eq!(course.sections[0].available_seats(), 2);
+        assert!(course.sections[0].is_selectable());
+        assert!(!course.sections[1].is_selectable());
+    }
+
+    #[tokio::test]
+    async fn fetches_the_preadvised_courses() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(PREADVICE_COURSES_PATH))
+            .respond_with(success(preadvised_courses()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let preadvised = client(&server).fetch_preadvised_courses().await.unwrap();
+        assert_eq!(preadvised.total_courses, 1);
+        assert_eq!(preadvised.courses[0].formal_code, "CSE1111");
+    }
+
+    #[tokio::test]
+    async fn unsuccessful_status_is_an_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(PREADVICE_COURSES_PATH))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "status": "error",
+                "message": "Section selection is closed"
+            })))
+            .mount(&server)
+            .await;
+
+        let err = client(&server)
+            .fetch_preadvised_courses()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::WindowClosed(_)), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn server_error_is_not_parsed() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(PREADVICE_COURSES_PATH))
+            .respond_with(ResponseTemplate::new(502).set_body_string("<html>Bad Gateway</html>"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let err = client(&server)
+            .fetch_preadvised_courses()
+            .await
+            .unwrap_err();
+        assert!(err.is_server_error(), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn waits_out_too_many_requests() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("{SECTIONS_PATH}/CSE1111")))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("{SECTIONS_PATH}/CSE1111")))
+            .respond_with(success(course_sections()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let started = std::time::Instant::now();
+        let course = client(&server)
+            .fetch_course_sections("CSE1111", "0112330000")
+            .await
+            .unwrap();
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert_eq!(course.sections.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn fetches_the_catalog_page_by_page() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(ALL_COURSES_PATH))
+            .and(query_param("page", "1"))
+            .and(query_param("limit", "100"))
+            .respond_with(success(catalog_page(0, 100)))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(ALL_COURSES_PATH))
+            .and(query_param("page", "2"))
+            .respond_with(success(catalog_page(100, 20)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let courses = client(&server)
+            .with_legacy_base_url(server.uri())
+            .fetch_all_courses()
+            .await
+            .unwrap();
+        assert_eq!(courses.len(), 120);
+        assert_eq!(courses[0].id, "course-0");
+        assert_eq!(courses[119].code, "CSE 119");
+    }
+
+    #[tokio::test]
+    async fn stops_when_the_catalog_ignores_paging() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(ALL_COURSES_PATH))
+            .respond_with(success(catalog_page(0, 100)))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let courses = client(&server)
+            .with_legacy_base_url(server.uri())
+            .fetch_all_courses()
+            .await
+            .unwrap();
+        assert_eq!(courses.len(), 100);
+    }
+
+    #[tokio::test]
+    async fn sends_back_the_cookies_the_server_sets() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(LOGIN_PATH))
+            .respond_with(
+                success(json!({
+                    "access_token": "access",
+                    "refresh_token": "refresh",
+                    "access_token_expires_at": "2099-01-01T00:00:00Z",
+                    "refresh_token_expires_at": "2099-01-01T00:00:00Z"
+                }))
+                .insert_header("Set-Cookie", "AWSALB=sticky; Path=/"),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(PREADVICE_COURSES_PATH))
+            .and(header("cookie", "AWSALB=sticky"))
+            .and(bearer_token("access"))
+            .respond_with(success(preadvised_courses()))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        client
+            .login(&LoginRequest {
+                user_id: "0112330000".to_string(),
+                password: "password".to_string(),
+                logout_other_sessions: false,
+            })
+            .await
+            .unwrap();
+        client.fetch_preadvised_courses().await.unwrap();
+        // Rebuilding the HTTP client keeps the jar, and with it the cookie.
+        let client = client.with_user_agent("test").unwrap();
+        client.fetch_preadvised_courses().await.unwrap();
+    }
+
+    #[test]
+    fn already_enrolled_message_is_classified() {
+        for message in [
+            "You are already enrolled in this course",
+            "Already registered for section B",
+            "Duplicate enrollment",
+        ] {
+            let e = ApiError::from_message("Course section action", message.to_string());
+            assert!(matches!(e, ApiError::AlreadyEnrolled(m) if m == message));
+        }
+        let e = ApiError::from_message("Course section action", "Section is full".to_string());
+        assert!(matches!(e, ApiError::SeatTaken(_)));
+    }
+
+    fn breaker_state(breaker: &CircuitBreaker) -> BreakerState {
+        *breaker.state.lock().unwrap()
+    }
+
+    #[tokio::test]
+    async fn dropped_probe_reopens_the_circuit() {
+        let breaker = CircuitBreaker::new(CircuitBreakerPolicy {
+            failure_threshold: 1,
+            cooldown: Duration::ZERO,
+        });
+        breaker.acquire().await.record(true);
+        assert!(matches!(breaker_state(&breaker), BreakerState::Open { .. }));
+
+        let probe = breaker.acquire().await;
+        assert!(matches!(breaker_state(&breaker), BreakerState::HalfOpen));
+        drop(probe);
+        assert!(matches!(breaker_state(&breaker), BreakerState::Open { .. }));
+
+        breaker.acquire().await.record(false);
+        assert!(matches!(
+            breaker_state(&breaker),
+            BreakerState::Closed { failures: 0 }
+        ));
     }
-    Ok(())
 }
@@ -0,0 +1,157 @@
+use std::{collections::HashMap, time::Duration};
+
+use futures_util::{Stream, StreamExt, stream};
+
+use crate::ucam_cloud_api::{ApiClient, ApiError, CourseSection, CourseSections, Result};
+
+/// Polls the sections of a course every `interval`, yielding every snapshot of them. The stream
+/// ends after the first error, [`ApiError::InvalidToken`] once the client's token is about to
+/// expire.
+pub fn subscribe_sections(
+    client: ApiClient,
+    course_code: String,
+    student_id: String,
+    interval: Duration,
+) -> impl Stream<Item = Result<CourseSections>> {
+    let state = Some((client, course_code, student_id, true));
+    stream::unfold(state, move |state| async move {
+        let (client, course_code, student_id, first) = state?;
+        if !first {
+            tokio::time::sleep(interval).await;
+        }
+        let result = if client.token().is_none_or(|token| token.expires_soon()) {
+            Err(ApiError::InvalidToken)
+        } else {
+            client
+                .fetch_course_sections(&course_code, &student_id)
+                .await
+        };
+        let next = result
+            .is_ok()
+            .then_some((client, course_code, student_id, false));
+        Some((result, next))
+    })
+}
+
+/// Polls the sections of a course every `interval`, calling `on_change` with the previous and
+/// current sections on the first poll and whenever the taken seats of any section change. Only
+/// returns on an error, [`ApiError::InvalidToken`] once the client's token is about to expire.
+pub async fn watch_course(
+    client: &ApiClient,
+    course_code: &str,
+    student_id: &str,
+    interval: Duration,
+    mut on_change: impl FnMut(Option<&CourseSections>, &CourseSections),
+) -> Result<()> {
+    let mut sections = std::pin::pin!(subscribe_sections(
+        client.clone(),
+        course_code.to_string(),
+        student_id.to_string(),
+        interval,
+    ));
+    let mut previous: Option<CourseSections> = None;
+    while let Some(current) = sections.next().await {
+        let current = current?;
+        let changed = previous.as_ref().is_none_or(|previous| {
+            current.sections.len() != previous.sections.len()
+                || !diff_sections(previous, &current).is_empty()
+        });
+        if changed {
+            on_change(previous.as_ref(), &current);
+            previous = Some(current);
+        }
+    }
+    Ok(())
+}
+
+/// A section whose taken seats changed between two polls, displayed like
+/// `B: 40/40 -> 39/40 (1 seat freed!)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeatChange {
+    pub section_id: u64,
+    pub section_name: String,
+    pub seats_taken_before: usize,
+    pub seats_taken: usize,
+    pub total_seats: usize,
+}
+
+impl std::fmt::Display for SeatChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}/{total} -> {}/{total}",
+            self.section_name,
+            self.seats_taken_before,
+            self.seats_taken,
+            total = self.total_seats
+        )?;
+        let plural = |n: usize| if n == 1 { "seat" } else { "seats" };
+        if self.seats_taken < self.seats_taken_before {
+            let freed = self.seats_taken_before - self.seats_taken;
+            write!(f, " ({freed} {} freed!)", plural(freed))
+        } else if self.seats_taken >= self.total_seats {
+            write!(f, " (FULL)")
+        } else {
+            let taken = self.seats_taken - self.seats_taken_before;
+            write!(f, " ({taken} {} taken)", plural(taken))
+        }
+    }
+}
+
+/// The sections of `cur` whose taken seats differ from `prev`, the ones missing from `prev` are
+/// left out.
+pub fn diff_sections(prev: &CourseSections, cur: &CourseSections) -> Vec<SeatChange> {
+    let prev: HashMap<_, _> = prev
+        .sections
+        .iter()
+        .map(|s| (s.section_id, s.seats_taken))
+        .collect();
+    cur.sections
+        .iter()
+        .filter_map(|s| {
+            let before = *prev.get(&s.section_id)?;
+            (before != s.seats_taken).then(|| SeatChange {
+                section_id: s.section_id,
+                section_name: s.section_name.clone(),
+                seats_taken_before: before,
+                seats_taken: s.seats_taken,
+                total_seats: s.total_seats,
+            })
+        })
+        .collect()
+}
+
+/// Describes the sections whose taken seats changed, eg. `Section B: 39/40 -> 40/40 (FULL)`,
+/// and the ones new since `previous`, or every section without `previous`.
+pub fn describe_seat_changes(
+    previous: Option<&CourseSections>,
+    current: &CourseSections,
+) -> Vec<String> {
+    let Some(previous) = previous else {
+        return current.sections.iter().map(describe_seats).collect();
+    };
+    let mut changes: Vec<_> = diff_sections(previous, current)
+        .iter()
+        .map(|change| format!("Section {change}"))
+        .collect();
+    let added = current.sections.iter().filter(|s| {
+        !previous
+            .sections
+            .iter()
+            .any(|p| p.section_id == s.section_id)
+    });
+    changes.extend(added.map(describe_seats));
+    changes
+}
+
+fn describe_seats(section: &CourseSection) -> String {
+    let full = if section.available_seats() == 0 {
+        " (FULL)"
+    } else {
+        ""
+    };
+    format!(
+        "Section {}: {}/{}{full}",
+        section.section_name, section.seats_taken, section.total_seats
+    )
+}